@@ -0,0 +1,71 @@
+//! Times building the `PhotoIndex` with near duplicates merged by image hash, as on
+//! every publish with `PHOTOJOURNALISM_PERCEPTUAL_HASH=true`, against building it
+//! without, for a growing number of photos.
+//!
+//! Run with `cargo bench --bench cluster`.
+
+use photojournalism::index::PhotoIndex;
+use photojournalism::parser::NewsPhoto;
+use photojournalism::phash::{ImageHashes, PhashConfig};
+use photojournalism::store::{MemoryStore, Store};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::time::{Duration, Instant};
+
+const PHOTOS_PER_FEED: usize = 40;
+const ROUNDS: u32 = 5;
+// how many of the photos are the same frame as run by another outlet
+const DUPLICATES: usize = 4;
+
+// the store and the hashes of `feeds` feeds, a few of whose photos are near duplicates
+fn feeds(feeds: usize, hashes: &ImageHashes) -> MemoryStore {
+    let mut rng = StdRng::seed_from_u64(feeds as u64);
+    let store = MemoryStore::default();
+    let mut frames: Vec<u64> = Vec::new();
+    for feed in 0..feeds {
+        let photos = (0..PHOTOS_PER_FEED)
+            .map(|i| {
+                let image_url = format!("https://example.com/{feed}/{i}.jpg");
+                let hash = match frames.len() {
+                    known if known > 0 && i % DUPLICATES == 0 => {
+                        frames[rng.gen_range(0..known)] ^ (1 << rng.gen_range(0..64))
+                    }
+                    _ => rng.r#gen(),
+                };
+                frames.push(hash);
+                hashes.insert(&image_url, Some(hash));
+                NewsPhoto {
+                    image_url,
+                    story_url: format!("https://example.com/{feed}/{i}"),
+                    ..NewsPhoto::new()
+                }
+            })
+            .collect();
+        store
+            .upsert_feed(&format!("https://example.com/{feed}/rss"), photos)
+            .unwrap();
+    }
+    store
+}
+
+fn time(build: impl Fn() -> PhotoIndex) -> (Duration, usize) {
+    let started = Instant::now();
+    let mut served = 0;
+    for _ in 0..ROUNDS {
+        served = build().counts().1;
+    }
+    (started.elapsed() / ROUNDS, served)
+}
+
+fn main() {
+    for count in [10, 50, 250, 1000] {
+        let hashes = ImageHashes::new(PhashConfig::default().max_distance);
+        let store = feeds(count, &hashes);
+        let (plain, all) = time(|| PhotoIndex::build(&store, None).unwrap());
+        let (clustered, served) = time(|| PhotoIndex::build(&store, Some(&hashes)).unwrap());
+        println!(
+            "{:>6} photos: {plain:?} without hashes, {clustered:?} with ({served} served of {all})",
+            count * PHOTOS_PER_FEED
+        );
+    }
+}
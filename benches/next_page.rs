@@ -0,0 +1,133 @@
+//! Times a page of `/api/next` while the loader keeps refreshing the photos, comparing
+//! the old read path (a global `Mutex`, flattened and shuffled on every request) with
+//! the published `PhotoIndex`.
+//!
+//! Run with `cargo bench --bench next_page`.
+
+use photojournalism::index::SharedIndex;
+use photojournalism::parser::NewsPhoto;
+use photojournalism::shuffler::randomize;
+use photojournalism::store::{MemoryStore, Store};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const FEEDS: usize = 60;
+const PHOTOS_PER_FEED: usize = 40;
+const READERS: usize = 8;
+const REQUESTS_PER_READER: usize = 2000;
+const PAGE_SIZE: usize = 6;
+
+fn photos(feed: usize, round: usize) -> Vec<NewsPhoto> {
+    (0..PHOTOS_PER_FEED)
+        .map(|i| NewsPhoto {
+            image_url: format!("https://example.com/{feed}/{round}/{i}.jpg"),
+            story_url: format!("https://example.com/{feed}/{round}/{i}"),
+            ..NewsPhoto::new()
+        })
+        .collect()
+}
+
+fn feed_url(feed: usize) -> String {
+    format!("https://example.com/{feed}/rss")
+}
+
+// runs `read` from every reader thread while `refresh` runs in a loop, and returns
+// the latency of every read
+fn measure(
+    read: impl Fn(u64, usize) -> usize + Sync,
+    refresh: impl Fn(usize) + Sync,
+) -> Vec<Duration> {
+    let done = AtomicBool::new(false);
+    thread::scope(|scope| {
+        scope.spawn(|| {
+            let mut round = 0;
+            while !done.load(Ordering::Relaxed) {
+                refresh(round);
+                round += 1;
+            }
+        });
+        let readers: Vec<_> = (0..READERS)
+            .map(|reader| {
+                let read = &read;
+                scope.spawn(move || {
+                    (0..REQUESTS_PER_READER)
+                        .map(|request| {
+                            let seed = ((reader * 31 + request) % 200) as u64;
+                            let started = Instant::now();
+                            let served = read(seed, (request % 10) * PAGE_SIZE);
+                            assert!(served <= PAGE_SIZE);
+                            started.elapsed()
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        let latencies = readers
+            .into_iter()
+            .flat_map(|reader| reader.join().unwrap())
+            .collect();
+        done.store(true, Ordering::Relaxed);
+        latencies
+    })
+}
+
+fn report(name: &str, mut latencies: Vec<Duration>) {
+    latencies.sort();
+    let at = |quantile: f64| latencies[((latencies.len() - 1) as f64 * quantile) as usize];
+    println!(
+        "{name:>6}: {} requests, p50 {:?}, p99 {:?}, max {:?}",
+        latencies.len(),
+        at(0.5),
+        at(0.99),
+        at(1.0)
+    );
+}
+
+fn main() {
+    // the read path before the index: every request locks, flattens and shuffles everything
+    let db = Mutex::new(
+        (0..FEEDS)
+            .map(|feed| (feed_url(feed), photos(feed, 0)))
+            .collect::<HashMap<_, _>>(),
+    );
+    let latencies = measure(
+        |seed, start| {
+            let db = db.lock().unwrap();
+            let photos: Vec<&NewsPhoto> = db.values().flatten().collect();
+            let shuffle = randomize(seed, photos.len());
+            let stop = (start + PAGE_SIZE).min(photos.len());
+            shuffle[start.min(stop)..stop]
+                .iter()
+                .filter_map(|i| photos.get(*i))
+                .count()
+        },
+        |round| {
+            let feed = round % FEEDS;
+            let fresh = photos(feed, round);
+            db.lock().unwrap().insert(feed_url(feed), fresh);
+        },
+    );
+    report("mutex", latencies);
+
+    // the published index: readers never wait on the loader, nor on each other
+    let store = MemoryStore::default();
+    for feed in 0..FEEDS {
+        store.upsert_feed(&feed_url(feed), photos(feed, 0)).unwrap();
+    }
+    let index = SharedIndex::default();
+    index.publish(&store).unwrap();
+    let latencies = measure(
+        |seed, start| index.load().page(seed, start, PAGE_SIZE, |_| true).len(),
+        |round| {
+            let feed = round % FEEDS;
+            store
+                .upsert_feed(&feed_url(feed), photos(feed, round))
+                .unwrap();
+            index.publish(&store).unwrap();
+        },
+    );
+    report("index", latencies);
+}
@@ -0,0 +1,190 @@
+use http_cache_reqwest::{CACacheManager, CacheManager, HttpResponse};
+use http_cache_semantics::CachePolicy;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time::{self, MissedTickBehavior};
+
+/// Where (and whether) fetched responses are kept on disk, and how much room they may take.
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+    // off for read-only containers, where every fetch then goes to the network
+    pub enabled: bool,
+    pub dir: PathBuf,
+    // bytes of cached content kept, once garbage collected
+    pub max_size: u64,
+    // seconds between garbage collections
+    pub gc_interval: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        // prefer the system tmp folder as opposed to `./` which is the CACacheManager default
+        Self {
+            enabled: true,
+            dir: [std::env::temp_dir(), "http-cacache".into()]
+                .iter()
+                .collect(),
+            max_size: 100 * 1024 * 1024,
+            gc_interval: 600,
+        }
+    }
+}
+
+/// What one garbage collection found, and how much of it was evicted.
+#[derive(Debug, Default, PartialEq)]
+pub struct Collected {
+    pub entries: usize,
+    pub evicted: usize,
+    pub size: u64,
+    pub freed: u64,
+}
+
+/// A cacache store which remembers when each entry was last used, so it can be trimmed
+/// back to its maximum size least recently used first.
+#[derive(Debug, Clone)]
+pub struct DiskCache {
+    manager: CACacheManager,
+    max_size: u64,
+    // unix millis of the last read or write of each key since startup;
+    // entries untouched since then count as last used when they were written
+    used: Arc<Mutex<HashMap<String, u128>>>,
+}
+
+// what http-cache expects of a CacheManager (its own alias is not exported by the middleware)
+type CacheResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+fn millis() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default()
+}
+
+impl DiskCache {
+    pub fn new(config: &CacheConfig) -> Self {
+        Self {
+            manager: CACacheManager {
+                path: config.dir.clone(),
+            },
+            max_size: config.max_size,
+            used: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.manager.path
+    }
+
+    fn touch(&self, key: &str, at: u128) {
+        if let Ok(mut used) = self.used.lock() {
+            used.insert(key.to_string(), at);
+        }
+    }
+
+    /// Evicts the least recently used entries until the cache fits within its maximum size.
+    pub fn collect(&self) -> Result<Collected, cacache::Error> {
+        let mut entries = cacache::index::ls(self.path()).collect::<Result<Vec<_>, _>>()?;
+        let mut collected = Collected {
+            entries: entries.len(),
+            size: entries.iter().map(|entry| entry.size as u64).sum(),
+            ..Collected::default()
+        };
+        if collected.size <= self.max_size {
+            return Ok(collected);
+        }
+
+        let used = self
+            .used
+            .lock()
+            .map(|used| used.clone())
+            .unwrap_or_default();
+        entries.sort_by_key(|entry| used.get(&entry.key).copied().unwrap_or(entry.time));
+
+        let mut evicted = 0;
+        while evicted < entries.len() && collected.size - collected.freed > self.max_size {
+            collected.freed += entries[evicted].size as u64;
+            evicted += 1;
+        }
+        let kept = entries.split_off(evicted);
+        collected.evicted = evicted;
+
+        // identical responses share their content, so only remove what nothing else points to
+        let shared: HashSet<String> = kept
+            .iter()
+            .map(|entry| entry.integrity.to_string())
+            .collect();
+        for entry in entries {
+            cacache::remove_sync(self.path(), &entry.key)?;
+            if !shared.contains(&entry.integrity.to_string()) {
+                cacache::remove_hash_sync(self.path(), &entry.integrity)?;
+            }
+            if let Ok(mut used) = self.used.lock() {
+                used.remove(&entry.key);
+            }
+        }
+        Ok(collected)
+    }
+}
+
+#[async_trait::async_trait]
+impl CacheManager for DiskCache {
+    async fn get(&self, cache_key: &str) -> CacheResult<Option<(HttpResponse, CachePolicy)>> {
+        let cached = self.manager.get(cache_key).await?;
+        if cached.is_some() {
+            self.touch(cache_key, millis());
+        }
+        Ok(cached)
+    }
+
+    async fn put(
+        &self,
+        cache_key: String,
+        response: HttpResponse,
+        policy: CachePolicy,
+    ) -> CacheResult<HttpResponse> {
+        self.touch(&cache_key, millis());
+        self.manager.put(cache_key, response, policy).await
+    }
+
+    async fn delete(&self, cache_key: &str) -> CacheResult<()> {
+        if let Ok(mut used) = self.used.lock() {
+            used.remove(cache_key);
+        }
+        self.manager.delete(cache_key).await
+    }
+}
+
+pub async fn background(cache: DiskCache, interval: u64) {
+    // trim the cache right away (it may have outgrown a smaller limit since the last run),
+    // then regularly after that
+    let mut ticker = time::interval(Duration::from_secs(interval.max(1)));
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    loop {
+        ticker.tick().await;
+        let trimmed = cache.clone();
+        match tokio::task::spawn_blocking(move || trimmed.collect()).await {
+            Ok(Ok(collected)) if collected.evicted > 0 => log::info!(
+                "http cache: evicted {} of {} entries ({} of {} bytes) from '{}'",
+                collected.evicted,
+                collected.entries,
+                collected.freed,
+                collected.size,
+                cache.path().display()
+            ),
+            Ok(Ok(collected)) => log::debug!(
+                "http cache: {} entries ({} bytes) in '{}'",
+                collected.entries,
+                collected.size,
+                cache.path().display()
+            ),
+            Ok(Err(err)) => log::warn!("http cache: '{}' {err}", cache.path().display()),
+            Err(err) => log::warn!("http cache: garbage collection failed {err}"),
+        }
+    }
+}
+
+#[path = "cache_test.rs"]
+#[cfg(test)]
+mod tests;
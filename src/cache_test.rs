@@ -0,0 +1,95 @@
+use super::*;
+
+// note that entries are written with the async writer, as CACacheManager does,
+// since the sync writer does not record their sizes in the index
+
+// a cache of its own for each test, emptied before use
+fn cache_at(name: &str, max_size: u64) -> DiskCache {
+    let dir = std::env::temp_dir().join(format!("photojournalism-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    DiskCache::new(&CacheConfig {
+        dir,
+        max_size,
+        ..CacheConfig::default()
+    })
+}
+
+fn keys(cache: &DiskCache) -> Vec<String> {
+    let mut keys: Vec<String> = cacache::index::ls(cache.path())
+        .filter_map(Result::ok)
+        .map(|entry| entry.key)
+        .collect();
+    keys.sort();
+    keys
+}
+
+#[tokio::test]
+async fn caches_within_their_size_are_left_alone() {
+    let cache = cache_at("cache-within", 300);
+    for key in ["a", "b", "c"] {
+        cacache::write(cache.path(), key, [key.as_bytes()[0]; 100])
+            .await
+            .unwrap();
+    }
+    assert_eq!(
+        cache.collect().unwrap(),
+        Collected {
+            entries: 3,
+            size: 300,
+            ..Collected::default()
+        }
+    );
+    assert_eq!(keys(&cache), ["a", "b", "c"]);
+    let _ = std::fs::remove_dir_all(cache.path());
+}
+
+#[tokio::test]
+async fn least_recently_used_entries_are_evicted_first() {
+    let cache = cache_at("cache-lru", 200);
+    for key in ["a", "b", "c"] {
+        cacache::write(cache.path(), key, [key.as_bytes()[0]; 100])
+            .await
+            .unwrap();
+    }
+    // "b" was written after "a", but "a" was read since
+    let written = millis();
+    cache.touch("a", written + 2);
+    cache.touch("b", written + 1);
+    cache.touch("c", written + 3);
+
+    assert_eq!(
+        cache.collect().unwrap(),
+        Collected {
+            entries: 3,
+            evicted: 1,
+            size: 300,
+            freed: 100
+        }
+    );
+    assert_eq!(keys(&cache), ["a", "c"]);
+    assert!(cacache::read_sync(cache.path(), "b").is_err());
+    assert!(cacache::read_sync(cache.path(), "a").is_ok());
+    let _ = std::fs::remove_dir_all(cache.path());
+}
+
+#[tokio::test]
+async fn shared_content_outlives_its_evicted_keys() {
+    let cache = cache_at("cache-shared", 100);
+    cacache::write(cache.path(), "old", [b'x'; 100])
+        .await
+        .unwrap();
+    cacache::write(cache.path(), "new", [b'x'; 100])
+        .await
+        .unwrap();
+    let written = millis();
+    cache.touch("old", written);
+    cache.touch("new", written + 1);
+
+    assert_eq!(cache.collect().unwrap().evicted, 1);
+    assert_eq!(keys(&cache), ["new"]);
+    assert_eq!(
+        cacache::read_sync(cache.path(), "new").unwrap(),
+        vec![b'x'; 100]
+    );
+    let _ = std::fs::remove_dir_all(cache.path());
+}
@@ -0,0 +1,94 @@
+use url::Url;
+
+// query parameters which only ever serve to track where a reader came from
+const TRACKING_PARAMS: [&str; 11] = [
+    "fbclid",
+    "gclid",
+    "dclid",
+    "msclkid",
+    "mc_cid",
+    "mc_eid",
+    "igshid",
+    "yclid",
+    "at_medium",
+    "at_campaign",
+    "_ga",
+];
+
+const TRACKING_PREFIXES: [&str; 2] = ["utm_", "at_custom"];
+
+// query parameters which publishers add to the links in their feeds to track readers,
+// but whose generic names could mean something else to an image server or cdn
+const REFERRAL_PARAMS: [&str; 6] = ["smid", "partner", "cmpid", "ocid", "ncid", "emc"];
+
+fn is_tracking(param: &str) -> bool {
+    let key = param.split('=').next().unwrap_or_default();
+    TRACKING_PARAMS.contains(&key) || TRACKING_PREFIXES.iter().any(|p| key.starts_with(p))
+}
+
+fn is_referral(param: &str) -> bool {
+    let key = param.split('=').next().unwrap_or_default();
+    is_tracking(param) || REFERRAL_PARAMS.contains(&key)
+}
+
+/// Reduces a story url to a canonical form, so that the same story linked from
+/// different places compares equal: tracking parameters and fragments are dropped,
+/// the host is lowercased, and trailing slashes are removed from the path.
+///
+/// Anything which does not parse as an absolute url is returned as-is.
+pub fn canonicalize(url: &str) -> String {
+    let Some(mut parsed) = parse(url) else {
+        return url.to_string();
+    };
+    strip_query(&mut parsed, is_referral);
+
+    let path = parsed.path().to_string();
+    if path.len() > 1 && path.ends_with('/') {
+        parsed.set_path(path.trim_end_matches('/'));
+    }
+
+    parsed.to_string()
+}
+
+/// Same as `canonicalize`, for the url of an image or video, which the server may well
+/// treat differently with a trailing slash or any parameter of its own: only parameters
+/// known to do nothing but track are dropped, and the path is left as it is.
+pub fn canonicalize_media(url: &str) -> String {
+    let Some(mut parsed) = parse(url) else {
+        return url.to_string();
+    };
+    strip_query(&mut parsed, is_tracking);
+    parsed.to_string()
+}
+
+// an absolute url, without its fragment (and with its host lowercased by the parser)
+fn parse(url: &str) -> Option<Url> {
+    let mut parsed = Url::parse(url.trim()).ok()?;
+    if parsed.cannot_be_a_base() {
+        return None;
+    }
+    parsed.set_fragment(None);
+    Some(parsed)
+}
+
+fn strip_query(parsed: &mut Url, strip: fn(&str) -> bool) {
+    // filter the raw query, rather than re-encoding every parameter that is kept
+    if let Some(query) = parsed.query() {
+        let params = query
+            .split('&')
+            .filter(|p| !p.is_empty())
+            .collect::<Vec<_>>();
+        if params.iter().any(|p| strip(p)) {
+            let kept = params
+                .into_iter()
+                .filter(|p| !strip(p))
+                .collect::<Vec<_>>()
+                .join("&");
+            parsed.set_query(if kept.is_empty() { None } else { Some(&kept) });
+        }
+    }
+}
+
+#[path = "canonical_test.rs"]
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,87 @@
+use super::*;
+
+#[test]
+fn tracking_parameters_are_removed() {
+    assert_eq!(
+        canonicalize("https://aeon.co/essays/going-cashless?utm_source=rss-feed"),
+        "https://aeon.co/essays/going-cashless"
+    );
+    assert_eq!(
+        canonicalize(
+            "https://www.nytimes.com/2023/11/24/world/gaza.html?smid=url-share&partner=rss&emc=rss"
+        ),
+        "https://www.nytimes.com/2023/11/24/world/gaza.html"
+    );
+    assert_eq!(
+        canonicalize(
+            "https://www.bbc.co.uk/news/uk-politics-43532916?at_medium=RSS&at_campaign=KARANGA"
+        ),
+        "https://www.bbc.co.uk/news/uk-politics-43532916"
+    );
+}
+
+#[test]
+fn meaningful_parameters_are_kept_verbatim() {
+    /*
+    Image urls often carry sizing parameters which must survive untouched,
+    including any encoding the publisher chose (e.g. commas in a list).
+     */
+
+    assert_eq!(
+        canonicalize("https://media.npr.org/assets/img/2023/11/24/gettyimages_wide.jpg?s=600"),
+        "https://media.npr.org/assets/img/2023/11/24/gettyimages_wide.jpg?s=600"
+    );
+    assert_eq!(
+        canonicalize_media(
+            "https://images.example.com/a.jpg?utm_source=rss&auto=format,compress&w=1200"
+        ),
+        "https://images.example.com/a.jpg?auto=format,compress&w=1200"
+    );
+}
+
+#[test]
+fn media_urls_only_lose_parameters_known_to_track() {
+    /*
+    An image server may do something with a parameter a story link would only
+    use for tracking (e.g. a `partner` crop), or serve something else without
+    a trailing slash, so images and videos keep both.
+     */
+
+    assert_eq!(
+        canonicalize_media(
+            "https://cdn.example.com/photos/1234/?partner=ap&ocid=7&fbclid=abc&gclid=def"
+        ),
+        "https://cdn.example.com/photos/1234/?partner=ap&ocid=7"
+    );
+    assert_eq!(
+        canonicalize_media("https://CDN.Example.com/clip.mp4#t=10"),
+        "https://cdn.example.com/clip.mp4"
+    );
+    assert_eq!(
+        canonicalize("https://www.example.com/story/?partner=rss&ocid=7"),
+        "https://www.example.com/story"
+    );
+}
+
+#[test]
+fn host_case_trailing_slashes_and_fragments_are_normalized() {
+    assert_eq!(
+        canonicalize("https://WWW.JapanTimes.co.jp/news/2023/11/24/story/#comments"),
+        "https://www.japantimes.co.jp/news/2023/11/24/story"
+    );
+    // the path itself is case-sensitive
+    assert_eq!(
+        canonicalize("https://www.japantimes.co.jp/uploads/265518.JPG"),
+        "https://www.japantimes.co.jp/uploads/265518.JPG"
+    );
+    assert_eq!(
+        canonicalize("https://www.france24.com/"),
+        "https://www.france24.com/"
+    );
+}
+
+#[test]
+fn relative_and_invalid_urls_are_left_alone() {
+    assert_eq!(canonicalize("/images/photo.jpg"), "/images/photo.jpg");
+    assert_eq!(canonicalize("not a url"), "not a url");
+}
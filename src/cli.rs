@@ -0,0 +1,98 @@
+use crate::discovery;
+use crate::feeds;
+use crate::http::{HttpClient, HttpConfig};
+use crate::opml;
+use crate::parser;
+use std::path::Path;
+
+const USAGE: &str = "usage: photojournalism [inspect <feed url or file> | discover <site url> | import <opml file> | export <feed list>]";
+
+pub fn is_command(args: &[String]) -> bool {
+    args.len() > 1
+}
+
+fn client() -> std::io::Result<HttpClient> {
+    HttpClient::new(&HttpConfig::default()).map_err(std::io::Error::other)
+}
+
+async fn inspect(source: &str) -> std::io::Result<()> {
+    match parser::inspect_feed(&client()?, source).await {
+        Ok((photos, report)) => {
+            for photo in photos {
+                match photo.as_debug_json() {
+                    Ok(json) => println!("{json}"),
+                    Err(err) => eprintln!("could not serialize {:#?}: {err}", photo),
+                }
+            }
+            // keep stdout to one photo per line, so the summary goes to stderr
+            match serde_json::to_string(&report) {
+                Ok(json) => eprintln!("{json}"),
+                Err(_) => eprintln!("{:#?}", report),
+            }
+            Ok(())
+        }
+        Err(err) => Err(std::io::Error::other(format!(
+            "could not parse feed at '{source}': {err}"
+        ))),
+    }
+}
+
+async fn discover(site: &str) -> std::io::Result<()> {
+    match discovery::discover(&client()?, site).await {
+        Ok(candidates) => {
+            if candidates.is_empty() {
+                eprintln!("no feeds advertised at '{site}'");
+            }
+            // already ranked, best first
+            for candidate in candidates {
+                match serde_json::to_string(&candidate) {
+                    Ok(json) => println!("{json}"),
+                    Err(err) => eprintln!("could not serialize {:#?}: {err}", candidate),
+                }
+            }
+            Ok(())
+        }
+        Err(err) => Err(std::io::Error::other(format!(
+            "could not discover feeds at '{site}': {err}"
+        ))),
+    }
+}
+
+fn import(path: &str) -> std::io::Result<()> {
+    // any list of feeds can be read, but only an opml one makes sense here
+    let feeds = feeds::read_feed_list(Path::new(path))?;
+    eprintln!("{} feeds imported from '{path}'", feeds.len());
+    print!(
+        "{}",
+        feeds::write_feed_catalog(&feeds).map_err(std::io::Error::other)?
+    );
+    Ok(())
+}
+
+fn export(path: &str) -> std::io::Result<()> {
+    let feeds = feeds::read_feed_list(Path::new(path))?;
+    println!("{}", opml::to_opml(&feeds)?);
+    Ok(())
+}
+
+pub async fn run(args: &[String]) -> std::io::Result<()> {
+    match args.get(1).map(|a| a.as_str()) {
+        Some("inspect") => match args.get(2) {
+            Some(source) => inspect(source).await,
+            None => Err(std::io::Error::other(USAGE)),
+        },
+        Some("discover") => match args.get(2) {
+            Some(site) => discover(site).await,
+            None => Err(std::io::Error::other(USAGE)),
+        },
+        Some("import") => match args.get(2) {
+            Some(path) => import(path),
+            None => Err(std::io::Error::other(USAGE)),
+        },
+        Some("export") => match args.get(2) {
+            Some(path) => export(path),
+            None => Err(std::io::Error::other(USAGE)),
+        },
+        _ => Err(std::io::Error::other(USAGE)),
+    }
+}
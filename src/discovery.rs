@@ -0,0 +1,98 @@
+use crate::error::FeedError;
+use crate::http::HttpClient;
+use crate::parser::{ExtractionReport, get_photos_from_feed};
+use scraper::{Html, Selector};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+const FEED_TYPES: [&str; 3] = [
+    "application/rss+xml",
+    "application/atom+xml",
+    "application/feed+json",
+];
+
+// the only type of feed the parser reads; the others are listed, but marked unsupported
+const RSS: &str = "application/rss+xml";
+
+/// A feed advertised by a site, along with how many photos it yielded when parsed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Candidate {
+    pub url: String,
+    pub mime_type: String,
+    pub title: Option<String>,
+    pub photos: usize,
+    pub report: Option<ExtractionReport>,
+    pub error: Option<FeedError>,
+}
+
+/// Finds every `<link rel="alternate">` to a feed in the html of a page,
+/// resolving relative hrefs against the url the page was fetched from.
+pub fn find_candidates(html: &str, base: &Url) -> Vec<Candidate> {
+    let mut results: Vec<Candidate> = Vec::new();
+    let Ok(selector) = Selector::parse(r#"link[rel~="alternate"][href][type]"#) else {
+        return results;
+    };
+    for elem in Html::parse_document(html).select(&selector) {
+        let mime_type = elem.value().attr("type").unwrap_or_default().trim();
+        if !FEED_TYPES.contains(&mime_type.to_lowercase().as_str()) {
+            continue;
+        }
+        let Some(url) = elem
+            .value()
+            .attr("href")
+            .and_then(|href| base.join(href.trim()).ok())
+        else {
+            continue;
+        };
+        if results.iter().any(|c| c.url == url.as_str()) {
+            continue;
+        }
+        results.push(Candidate {
+            url: url.to_string(),
+            mime_type: mime_type.to_lowercase(),
+            title: elem.value().attr("title").map(|t| t.to_string()),
+            photos: 0,
+            report: None,
+            error: None,
+        });
+    }
+    results
+}
+
+/// Most photos first; candidates which could not be parsed go last.
+pub fn rank(candidates: &mut [Candidate]) {
+    candidates.sort_by_key(|c| (c.error.is_some(), std::cmp::Reverse(c.photos)));
+}
+
+/// Fetches the page at `site_url`, and test-parses every feed it advertises.
+pub async fn discover(client: &HttpClient, site_url: &str) -> Result<Vec<Candidate>, FeedError> {
+    let base = Url::parse(site_url).map_err(|err| FeedError::Request {
+        message: format!("invalid site url: {err}"),
+    })?;
+    let content = client.get(base.as_str()).await?;
+    let html = String::from_utf8_lossy(&content);
+
+    let mut candidates = find_candidates(&html, &base);
+    for candidate in candidates.iter_mut() {
+        if candidate.mime_type != RSS {
+            // atom and json feeds are advertised, but not something the parser reads
+            candidate.error = Some(FeedError::UnsupportedFormat {
+                message: candidate.mime_type.clone(),
+            });
+            continue;
+        }
+        match get_photos_from_feed(client, &candidate.url).await {
+            Ok((photos, report)) => {
+                candidate.photos = photos.len();
+                candidate.report = Some(report);
+            }
+            Err(err) => candidate.error = Some(err),
+        }
+    }
+    rank(&mut candidates);
+    Ok(candidates)
+}
+
+#[path = "discovery_test.rs"]
+#[cfg(test)]
+mod tests;
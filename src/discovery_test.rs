@@ -0,0 +1,128 @@
+use super::*;
+use crate::cache::CacheConfig;
+use crate::http::HttpConfig;
+use crate::test_server::{reply, response, serve};
+
+#[test]
+fn feed_links_are_found_and_resolved() {
+    /*
+    Sites advertise their feeds in the <head> of the homepage, often with
+    relative urls, and alongside links which are not feeds at all.
+     */
+
+    let html = r#"<!DOCTYPE html>
+    <html><head>
+      <title>Example News</title>
+      <link rel="stylesheet" href="/style.css">
+      <link rel="alternate" hreflang="fr" href="https://example.com/fr/">
+      <link rel="alternate" type="application/rss+xml" title="Top Stories" href="/rss/top.xml">
+      <link rel="alternate" type="application/atom+xml" title="World" href="https://feeds.example.com/world.atom">
+      <link rel="alternate" type="application/feed+json" href="feed.json">
+      <link rel="alternate" type="application/rss+xml" title="Duplicate" href="https://example.com/rss/top.xml">
+    </head><body></body></html>"#;
+
+    let base = Url::parse("https://example.com/news/").unwrap();
+    let candidates = find_candidates(html, &base);
+    let urls = candidates
+        .iter()
+        .map(|c| c.url.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        urls,
+        vec![
+            "https://example.com/rss/top.xml",
+            "https://feeds.example.com/world.atom",
+            "https://example.com/news/feed.json",
+        ]
+    );
+    assert_eq!(candidates[0].title, Some("Top Stories".to_string()));
+    assert_eq!(candidates[2].mime_type, "application/feed+json");
+}
+
+#[test]
+fn candidates_are_ranked_by_photo_yield() {
+    let base = Url::parse("https://example.com/").unwrap();
+    let html = r#"<link rel="alternate" type="application/rss+xml" href="/a.xml">
+      <link rel="alternate" type="application/rss+xml" href="/b.xml">
+      <link rel="alternate" type="application/rss+xml" href="/c.xml">"#;
+    let mut candidates = find_candidates(html, &base);
+    candidates[0].photos = 3;
+    candidates[1].error = Some(FeedError::Status {
+        code: 404,
+        retry_after: None,
+    });
+    candidates[2].photos = 12;
+
+    rank(&mut candidates);
+    let urls = candidates
+        .iter()
+        .map(|c| c.url.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        urls,
+        vec![
+            "https://example.com/c.xml",
+            "https://example.com/a.xml",
+            "https://example.com/b.xml",
+        ]
+    );
+}
+
+// serves a homepage advertising every kind of feed, and only the rss feed itself,
+// so fetching any other would fail with a 404
+async fn serve_site() -> String {
+    let home = r#"<html><head>
+      <link rel="alternate" type="application/atom+xml" href="/world.atom">
+      <link rel="alternate" type="application/rss+xml" title="Essays" href="/rss">
+      <link rel="alternate" type="application/feed+json" href="/feed.json">
+      <link rel="alternate" type="application/rss+xml" title="Gone" href="/missing.xml">
+    </head><body></body></html>"#;
+    let address = serve(move |request, socket| async move {
+        let body = match request.path.as_str() {
+            "/" => Some(home.to_string()),
+            "/rss" => std::fs::read_to_string("tests/fixtures/aeon.xml").ok(),
+            _ => None,
+        };
+        let response = match body {
+            Some(body) => response("200 OK", body),
+            None => response("404 Not Found", ""),
+        };
+        reply(socket, response).await;
+    })
+    .await;
+    format!("http://{address}/")
+}
+
+#[tokio::test]
+async fn sites_are_discovered_without_fetching_what_cannot_be_parsed() {
+    let site = serve_site().await;
+    let client = HttpClient::new(&HttpConfig {
+        cache: CacheConfig {
+            enabled: false,
+            ..CacheConfig::default()
+        },
+        ..HttpConfig::default()
+    })
+    .unwrap();
+    let candidates = discover(&client, &site).await.unwrap();
+    let found = candidates
+        .iter()
+        .map(|c| {
+            (
+                c.url.trim_start_matches(&site),
+                c.error.as_ref().map(|e| e.kind()),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        found,
+        vec![
+            ("rss", None),
+            ("world.atom", Some("unsupported_format")),
+            ("feed.json", Some("unsupported_format")),
+            ("missing.xml", Some("status")),
+        ]
+    );
+    assert!(candidates[0].photos > 0);
+    assert_eq!(candidates[0].title, Some("Essays".to_string()));
+}
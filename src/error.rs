@@ -0,0 +1,182 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Everything that can go wrong between requesting a feed and parsing its channel,
+/// grouped so that the loader can decide what to do about each kind of failure.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FeedError {
+    /// The host could not be resolved, or refused the connection.
+    Connect { message: String },
+    /// The request did not complete in time.
+    Timeout,
+    /// The server answered with something other than success, possibly
+    /// asking (with `Retry-After`) to wait a number of seconds before trying again.
+    Status {
+        code: u16,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        retry_after: Option<u64>,
+    },
+    /// The response body exceeded the configured limit (in bytes).
+    TooLarge { limit: usize },
+    /// The response body could not be decoded as text.
+    Encoding { message: String },
+    /// The response body is not well-formed xml.
+    Parse { message: String },
+    /// The response body is well-formed, but not an rss feed (e.g. html).
+    UnsupportedFormat { message: String },
+    /// Any other failure while making the request.
+    Request { message: String },
+}
+
+impl FeedError {
+    /// A short, stable name for this kind of error, suitable for counting.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            FeedError::Connect { .. } => "connect",
+            FeedError::Timeout => "timeout",
+            FeedError::Status { .. } => "status",
+            FeedError::TooLarge { .. } => "too_large",
+            FeedError::Encoding { .. } => "encoding",
+            FeedError::Parse { .. } => "parse",
+            FeedError::UnsupportedFormat { .. } => "unsupported_format",
+            FeedError::Request { .. } => "request",
+        }
+    }
+
+    /// Seconds the server asked to wait before trying again, if any.
+    pub fn retry_after(&self) -> Option<u64> {
+        match self {
+            FeedError::Status { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+
+    /// Whether trying again later might succeed without the publisher changing anything.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            FeedError::Connect { .. } | FeedError::Timeout | FeedError::Request { .. } => true,
+            FeedError::Status { code, .. } => *code == 429 || *code >= 500,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for FeedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FeedError::Connect { message } => write!(f, "could not connect: {message}"),
+            FeedError::Timeout => write!(f, "timed out"),
+            FeedError::Status { code, .. } => write!(f, "http status {code}"),
+            FeedError::TooLarge { limit } => write!(f, "response larger than {limit} bytes"),
+            FeedError::Encoding { message } => write!(f, "could not decode response: {message}"),
+            FeedError::Parse { message } => write!(f, "could not parse xml: {message}"),
+            FeedError::UnsupportedFormat { message } => write!(f, "unsupported format: {message}"),
+            FeedError::Request { message } => write!(f, "request failed: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for FeedError {}
+
+impl From<&reqwest::Error> for FeedError {
+    fn from(err: &reqwest::Error) -> Self {
+        if let Some(err) = find_feed_error(err) {
+            err.clone()
+        } else if err.is_timeout() {
+            FeedError::Timeout
+        } else if err.is_connect() {
+            FeedError::Connect {
+                message: err.to_string(),
+            }
+        } else if let Some(status) = err.status() {
+            FeedError::Status {
+                code: status.as_u16(),
+                retry_after: None,
+            }
+        } else if err.is_decode() {
+            FeedError::Encoding {
+                message: err.to_string(),
+            }
+        } else {
+            FeedError::Request {
+                message: err.to_string(),
+            }
+        }
+    }
+}
+
+impl From<reqwest::Error> for FeedError {
+    fn from(err: reqwest::Error) -> Self {
+        (&err).into()
+    }
+}
+
+fn find_reqwest_error<'a>(
+    err: &'a (dyn std::error::Error + 'static),
+) -> Option<&'a reqwest::Error> {
+    // middleware (e.g. the http cache) wraps the underlying error of the request
+    let mut current = Some(err);
+    while let Some(err) = current {
+        if let Some(err) = err.downcast_ref::<reqwest::Error>() {
+            return Some(err);
+        }
+        if let Some(reqwest_middleware::Error::Reqwest(err)) =
+            err.downcast_ref::<reqwest_middleware::Error>()
+        {
+            return Some(err);
+        }
+        current = err.source();
+    }
+    None
+}
+
+fn find_feed_error<'a>(err: &'a (dyn std::error::Error + 'static)) -> Option<&'a FeedError> {
+    // e.g. a body over the limit, refused by middleware beneath the http cache
+    std::iter::successors(Some(err), |err| err.source()).find_map(|err| err.downcast_ref())
+}
+
+impl From<reqwest_middleware::Error> for FeedError {
+    fn from(err: reqwest_middleware::Error) -> Self {
+        match err {
+            reqwest_middleware::Error::Reqwest(err) => err.into(),
+            reqwest_middleware::Error::Middleware(err) => {
+                if let Some(feed_error) = find_feed_error(err.as_ref()) {
+                    feed_error.clone()
+                } else if let Some(err) = find_reqwest_error(err.as_ref()) {
+                    err.into()
+                } else {
+                    FeedError::Request {
+                        message: err.to_string(),
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl From<rss::Error> for FeedError {
+    fn from(err: rss::Error) -> Self {
+        match err {
+            rss::Error::Utf8(err) => FeedError::Encoding {
+                message: err.to_string(),
+            },
+            rss::Error::Xml(quick_xml::Error::Encoding(err)) => FeedError::Encoding {
+                message: err.to_string(),
+            },
+            rss::Error::Xml(err) => FeedError::Parse {
+                message: err.to_string(),
+            },
+            rss::Error::InvalidStartTag => FeedError::UnsupportedFormat {
+                message: err.to_string(),
+            },
+            rss::Error::Eof => FeedError::Parse {
+                message: err.to_string(),
+            },
+        }
+    }
+}
+
+#[path = "error_test.rs"]
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,77 @@
+use super::*;
+use rss::Channel;
+use std::str::FromStr;
+
+#[test]
+fn parse_errors_are_classified_by_kind() {
+    /*
+    A feed url which serves an html page (e.g. after a site redesign) is
+    well-formed enough to read, but is not something the parser supports,
+    while a truncated or broken document is a parse error proper.
+     */
+
+    let html = Channel::from_str("<html><head><title>Moved</title></head></html>");
+    let err: FeedError = html.unwrap_err().into();
+    assert_eq!(err.kind(), "unsupported_format");
+    assert!(!err.is_transient());
+
+    let truncated = Channel::from_str(r#"<rss version="2.0"><channel><title>t</title>"#);
+    let err: FeedError = truncated.unwrap_err().into();
+    assert_eq!(err.kind(), "parse");
+
+    let invalid = Channel::read_from(
+        &b"<rss version=\"2.0\"><channel><title>\xff\xfe</title></channel></rss>"[..],
+    );
+    let err: FeedError = invalid.unwrap_err().into();
+    assert_eq!(err.kind(), "encoding");
+}
+
+#[test]
+fn only_some_errors_are_worth_retrying() {
+    assert!(FeedError::Timeout.is_transient());
+    assert!(
+        FeedError::Status {
+            code: 503,
+            retry_after: None,
+        }
+        .is_transient()
+    );
+    assert!(
+        FeedError::Status {
+            code: 429,
+            retry_after: None,
+        }
+        .is_transient()
+    );
+    assert!(
+        !FeedError::Status {
+            code: 404,
+            retry_after: None,
+        }
+        .is_transient()
+    );
+    assert!(!FeedError::TooLarge { limit: 1024 }.is_transient());
+
+    assert_eq!(
+        serde_json::to_string(&FeedError::Status {
+            code: 404,
+            retry_after: None,
+        })
+        .unwrap(),
+        r###"{"kind":"status","code":404}"###
+    );
+}
+
+#[test]
+fn retry_after_is_only_serialized_when_given() {
+    let throttled = FeedError::Status {
+        code: 429,
+        retry_after: Some(120),
+    };
+    assert_eq!(throttled.retry_after(), Some(120));
+    assert_eq!(
+        serde_json::to_string(&throttled).unwrap(),
+        r###"{"kind":"status","code":429,"retry_after":120}"###
+    );
+    assert_eq!(FeedError::Timeout.retry_after(), None);
+}
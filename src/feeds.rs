@@ -0,0 +1,384 @@
+use crate::opml;
+use reqwest::header::{HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::fs::{read, read_to_string};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tokio::signal::unix::{SignalKind, signal};
+use tokio::sync::watch;
+use tokio::time::{self, MissedTickBehavior};
+use toml::Spanned;
+use url::Url;
+
+/// A feed to fetch, as configured in the list of feeds.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Feed {
+    pub url: String,
+    // seconds between fetches, overriding whatever the feed itself suggests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interval: Option<u64>,
+    // the rest can only be set in a catalog
+    // seconds after which photos from the feed are too old to serve, 0 for no limit,
+    // overriding the global maximum age
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_age: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub section: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    // relative importance of the feed, 1 unless set otherwise
+    #[serde(default = "default_weight", skip_serializing_if = "is_default_weight")]
+    pub weight: u32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    // sent with every request for the feed (e.g. an api key some publishers require)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+fn is_default_weight(weight: &u32) -> bool {
+    *weight == default_weight()
+}
+
+impl Feed {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            interval: None,
+            max_age: None,
+            name: None,
+            section: None,
+            language: None,
+            weight: default_weight(),
+            tags: Vec::new(),
+            headers: BTreeMap::new(),
+        }
+    }
+}
+
+/// Parses the plain text list of feeds: one url per line, optionally followed by
+/// the number of seconds between fetches; lines starting with `#` are comments.
+pub fn parse_feed_list(text: &str) -> Vec<Feed> {
+    text.lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let url = fields.next()?;
+            let interval = fields.next().and_then(|secs| match secs.parse() {
+                Ok(secs) => Some(secs),
+                Err(_) => {
+                    log::warn!("feed list: ignoring invalid interval '{secs}' for '{url}'");
+                    None
+                }
+            });
+            Some(Feed {
+                interval,
+                ..Feed::new(url)
+            })
+        })
+        .collect()
+}
+
+/// Where a catalog went wrong, counting lines and columns from 1.
+#[derive(Debug, PartialEq)]
+pub struct CatalogError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+// the line and column of a byte offset into the text
+fn position(text: &str, offset: usize) -> (usize, usize) {
+    let before = text.get(..offset).unwrap_or(text);
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or_default();
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+impl CatalogError {
+    pub(crate) fn at(text: &str, offset: usize, message: String) -> Self {
+        let (line, column) = position(text, offset);
+        Self {
+            line,
+            column,
+            message,
+        }
+    }
+}
+
+impl fmt::Display for CatalogError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+impl Error for CatalogError {}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Catalog {
+    #[serde(default)]
+    feed: Vec<CatalogEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CatalogEntry {
+    url: Spanned<String>,
+    interval: Option<Spanned<u64>>,
+    max_age: Option<u64>,
+    name: Option<String>,
+    section: Option<String>,
+    language: Option<String>,
+    weight: Option<Spanned<u32>>,
+    #[serde(default)]
+    tags: Vec<String>,
+    headers: Option<Spanned<BTreeMap<String, String>>>,
+}
+
+/// Parses a TOML catalog of feeds, one `[[feed]]` table per feed, e.g.
+///
+/// ```toml
+/// [[feed]]
+/// url = "https://www.france24.com/en/rss"
+/// name = "France 24"
+/// section = "world"
+/// language = "en"
+/// interval = 1800
+/// max_age = 86400
+/// weight = 2
+/// tags = ["news", "europe"]
+/// headers = { "X-Api-Key" = "..." }
+/// ```
+pub fn parse_feed_catalog(text: &str) -> Result<Vec<Feed>, CatalogError> {
+    let catalog: Catalog = toml::from_str(text).map_err(|err| {
+        let offset = err.span().map(|span| span.start).unwrap_or_default();
+        CatalogError::at(text, offset, err.message().to_string())
+    })?;
+
+    let mut feeds: Vec<Feed> = Vec::new();
+    let mut first_seen: HashMap<String, usize> = HashMap::new();
+    for entry in catalog.feed {
+        let url = entry.url.get_ref().trim();
+        let invalid = |span: std::ops::Range<usize>, message: String| {
+            CatalogError::at(text, span.start, message)
+        };
+        if !Url::parse(url).is_ok_and(|parsed| ["http", "https"].contains(&parsed.scheme())) {
+            return Err(invalid(
+                entry.url.span(),
+                format!("invalid feed url '{url}'"),
+            ));
+        }
+        let (line, _) = position(text, entry.url.span().start);
+        if let Some(first) = first_seen.insert(url.to_string(), line) {
+            return Err(invalid(
+                entry.url.span(),
+                format!("duplicate feed url '{url}', first listed on line {first}"),
+            ));
+        }
+        if let Some(interval) = &entry.interval
+            && *interval.get_ref() == 0
+        {
+            return Err(invalid(
+                interval.span(),
+                "interval must be a positive number of seconds".to_string(),
+            ));
+        }
+        if let Some(weight) = &entry.weight
+            && *weight.get_ref() == 0
+        {
+            return Err(invalid(
+                weight.span(),
+                "weight must be at least 1".to_string(),
+            ));
+        }
+        if let Some(headers) = &entry.headers {
+            for (name, value) in headers.get_ref() {
+                if HeaderName::from_bytes(name.as_bytes()).is_err()
+                    || HeaderValue::from_str(value).is_err()
+                {
+                    return Err(invalid(headers.span(), format!("invalid header '{name}'")));
+                }
+            }
+        }
+
+        feeds.push(Feed {
+            interval: entry.interval.map(Spanned::into_inner),
+            max_age: entry.max_age,
+            name: entry.name,
+            section: entry.section,
+            language: entry.language,
+            weight: entry.weight.map(Spanned::into_inner).unwrap_or(1),
+            tags: entry.tags,
+            headers: entry.headers.map(Spanned::into_inner).unwrap_or_default(),
+            ..Feed::new(url)
+        });
+    }
+    Ok(feeds)
+}
+
+#[derive(Serialize)]
+struct CatalogOut<'a> {
+    feed: &'a [Feed],
+}
+
+/// Writes the feeds as a TOML catalog, which `parse_feed_catalog` reads back.
+pub fn write_feed_catalog(feeds: &[Feed]) -> Result<String, toml::ser::Error> {
+    toml::to_string(&CatalogOut { feed: feeds })
+}
+
+/// Reads the list of feeds: a TOML catalog if the file ends in `.toml`, an OPML
+/// subscription list if it ends in `.opml`, otherwise the plain text list.
+pub fn read_feed_list(path: &Path) -> std::io::Result<Vec<Feed>> {
+    let invalid = |err| std::io::Error::new(std::io::ErrorKind::InvalidData, err);
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => parse_feed_catalog(&read_to_string(path)?).map_err(invalid),
+        Some("opml") => opml::parse_opml(&read_to_string(path)?).map_err(invalid),
+        _ => Ok(parse_feed_list(&readable_lines(&read(path)?))),
+    }
+}
+
+// a line of the plain text list which is not valid utf-8 is skipped, rather than the whole list
+fn readable_lines(bytes: &[u8]) -> String {
+    bytes
+        .split(|byte| *byte == b'\n')
+        .enumerate()
+        .filter_map(|(number, line)| match std::str::from_utf8(line) {
+            Ok(line) => Some(line),
+            Err(err) => {
+                log::warn!("feed list: skipping line {}: {err}", number + 1);
+                None
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// What changed between two versions of the list of feeds, by url.
+#[derive(Debug, Default, PartialEq)]
+pub struct FeedListDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    // still listed, but with different settings
+    pub changed: Vec<String>,
+}
+
+impl FeedListDiff {
+    pub fn new(old: &[Feed], new: &[Feed]) -> Self {
+        let find = |feeds: &[Feed], url: &str| feeds.iter().find(|feed| feed.url == url).cloned();
+        let mut diff = Self::default();
+        for feed in new {
+            match find(old, &feed.url) {
+                None => diff.added.push(feed.url.clone()),
+                Some(before) if before != *feed => diff.changed.push(feed.url.clone()),
+                Some(_) => {}
+            }
+        }
+        for feed in old {
+            if find(new, &feed.url).is_none() {
+                diff.removed.push(feed.url.clone());
+            }
+        }
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl fmt::Display for FeedListDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let urls = |urls: &[String]| {
+            urls.iter()
+                .map(|url| format!("'{url}'"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let mut parts = Vec::new();
+        for (what, list) in [
+            ("added", &self.added),
+            ("removed", &self.removed),
+            ("changed", &self.changed),
+        ] {
+            if !list.is_empty() {
+                parts.push(format!("{what} {}", urls(list)));
+            }
+        }
+        write!(f, "{}", parts.join("; "))
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+}
+
+/// Re-reads the list of feeds whenever the file changes (checking every `interval` seconds)
+/// or the process receives SIGHUP, and publishes it to the loader if any feed changed.
+pub async fn watch_feed_list(path: PathBuf, feeds: watch::Sender<Vec<Feed>>, interval: u64) {
+    let mut ticker = time::interval(Duration::from_secs(interval.max(1)));
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => Some(hangup),
+        Err(err) => {
+            log::warn!("feed list: cannot reload on SIGHUP ({err}), watching the file only");
+            None
+        }
+    };
+    let mut last_modified = modified(&path);
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {
+                let now_modified = modified(&path);
+                if now_modified == last_modified {
+                    continue;
+                }
+                last_modified = now_modified;
+            }
+            Some(()) = async { hangup.as_mut()?.recv().await } => {
+                log::info!("feed list: reloading '{}' on SIGHUP", path.display());
+            }
+        }
+
+        // a list that cannot be read (e.g. while it is being rewritten) leaves things as they are
+        let list = match read_feed_list(&path) {
+            Ok(list) => list,
+            Err(err) => {
+                log::warn!("feed list: cannot reload '{}': {err}", path.display());
+                continue;
+            }
+        };
+        let diff = FeedListDiff::new(&feeds.borrow(), &list);
+        if diff.is_empty() {
+            continue;
+        }
+        log::info!("feed list: {diff}");
+        if feeds.send(list).is_err() {
+            // nothing is loading feeds anymore
+            return;
+        }
+    }
+}
+
+#[path = "feeds_test.rs"]
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,186 @@
+use super::*;
+
+#[test]
+fn plain_text_list_with_optional_intervals() {
+    let list = "# RSS feeds with photos
+https://rss.nytimes.com/services/xml/rss/nyt/HomePage.xml 300
+
+https://aeon.co/feed.rss\t86400
+  https://www.france24.com/en/rss
+https://www.japantimes.co.jp/feed/ hourly
+";
+    assert_eq!(
+        parse_feed_list(list),
+        vec![
+            Feed {
+                interval: Some(300),
+                ..Feed::new("https://rss.nytimes.com/services/xml/rss/nyt/HomePage.xml")
+            },
+            Feed {
+                interval: Some(86400),
+                ..Feed::new("https://aeon.co/feed.rss")
+            },
+            Feed::new("https://www.france24.com/en/rss"),
+            // an interval which is not a number of seconds is ignored
+            Feed::new("https://www.japantimes.co.jp/feed/"),
+        ]
+    );
+}
+
+#[test]
+fn the_bundled_feed_list_parses() {
+    let feeds = read_feed_list(Path::new("feeds.txt")).unwrap();
+    assert!(!feeds.is_empty());
+    assert!(feeds.iter().all(|feed| feed.url.starts_with("https://")));
+}
+
+#[test]
+fn unreadable_lines_are_skipped_rather_than_the_whole_list() {
+    let path = std::env::temp_dir().join(format!(
+        "photojournalism-unreadable-{}.txt",
+        std::process::id()
+    ));
+    std::fs::write(
+        &path,
+        b"https://aeon.co/feed.rss\nhttps://example.com/\xff\xfe\nhttps://www.france24.com/en/rss\n",
+    )
+    .unwrap();
+    let feeds = read_feed_list(&path).unwrap();
+    let urls: Vec<_> = feeds.iter().map(|feed| feed.url.as_str()).collect();
+    assert_eq!(
+        urls,
+        vec![
+            "https://aeon.co/feed.rss",
+            "https://www.france24.com/en/rss"
+        ]
+    );
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn reloaded_lists_are_compared_by_url() {
+    let old = parse_feed_list(
+        "https://aeon.co/feed.rss 86400
+https://www.france24.com/en/rss
+https://www.japantimes.co.jp/feed/
+",
+    );
+    let new = parse_feed_list(
+        "https://aeon.co/feed.rss 3600
+https://www.japantimes.co.jp/feed/
+https://www.theguardian.com/world/rss
+",
+    );
+    let diff = FeedListDiff::new(&old, &new);
+    assert_eq!(
+        diff,
+        FeedListDiff {
+            added: vec!["https://www.theguardian.com/world/rss".to_string()],
+            removed: vec!["https://www.france24.com/en/rss".to_string()],
+            changed: vec!["https://aeon.co/feed.rss".to_string()],
+        }
+    );
+    assert_eq!(
+        diff.to_string(),
+        "added 'https://www.theguardian.com/world/rss'; removed 'https://www.france24.com/en/rss'; changed 'https://aeon.co/feed.rss'"
+    );
+    assert!(FeedListDiff::new(&new, &new).is_empty());
+}
+
+#[test]
+fn catalogs_carry_per_feed_settings() {
+    let catalog = r#"# RSS feeds with photos
+[[feed]]
+url = "https://www.france24.com/en/rss"
+name = "France 24"
+section = "world"
+language = "en"
+interval = 1800
+weight = 2
+tags = ["news", "europe"]
+headers = { "X-Api-Key" = "secret" }
+
+[[feed]]
+url = "https://aeon.co/feed.rss"
+"#;
+    assert_eq!(
+        parse_feed_catalog(catalog),
+        Ok(vec![
+            Feed {
+                interval: Some(1800),
+                name: Some("France 24".to_string()),
+                section: Some("world".to_string()),
+                language: Some("en".to_string()),
+                weight: 2,
+                tags: vec!["news".to_string(), "europe".to_string()],
+                headers: BTreeMap::from([("X-Api-Key".to_string(), "secret".to_string())]),
+                ..Feed::new("https://www.france24.com/en/rss")
+            },
+            Feed::new("https://aeon.co/feed.rss"),
+        ])
+    );
+}
+
+#[test]
+fn catalog_errors_point_at_their_line() {
+    let error_line = |catalog: &str| parse_feed_catalog(catalog).map_err(|err| err.line);
+
+    // not valid toml
+    assert_eq!(
+        error_line("[[feed]]\nurl = \"https://aeon.co/feed.rss\"\nname = France\n"),
+        Err(3)
+    );
+    // a setting which does not exist
+    assert_eq!(
+        error_line("[[feed]]\nurl = \"https://aeon.co/feed.rss\"\ntitle = \"Aeon\"\n"),
+        Err(3)
+    );
+    // a setting of the wrong type
+    assert_eq!(
+        error_line("[[feed]]\nurl = \"https://aeon.co/feed.rss\"\n\ninterval = \"hourly\"\n"),
+        Err(4)
+    );
+    assert_eq!(error_line("[[feed]]\nname = \"Aeon\"\n"), Err(1));
+    assert_eq!(error_line("[[feed]]\nurl = \"aeon.co/feed.rss\"\n"), Err(2));
+    assert_eq!(
+        error_line("[[feed]]\nurl = \"https://aeon.co/feed.rss\"\nweight = 0\n"),
+        Err(3)
+    );
+    assert_eq!(
+        error_line(
+            "[[feed]]\nurl = \"https://aeon.co/feed.rss\"\nheaders = { \"Bad Name\" = \"x\" }\n"
+        ),
+        Err(3)
+    );
+
+    let duplicate = parse_feed_catalog(
+        "[[feed]]\nurl = \"https://aeon.co/feed.rss\"\n\n[[feed]]\nurl = \"https://aeon.co/feed.rss\"\n",
+    );
+    assert_eq!(
+        duplicate,
+        Err(CatalogError {
+            line: 5,
+            column: 7,
+            message: "duplicate feed url 'https://aeon.co/feed.rss', first listed on line 2"
+                .to_string()
+        })
+    );
+}
+
+#[test]
+fn catalogs_are_written_back_as_they_are_read() {
+    let feeds = vec![
+        Feed {
+            name: Some("France 24".to_string()),
+            section: Some("world".to_string()),
+            weight: 2,
+            tags: vec!["news".to_string()],
+            headers: BTreeMap::from([("X-Api-Key".to_string(), "secret".to_string())]),
+            ..Feed::new("https://www.france24.com/en/rss")
+        },
+        Feed::new("https://aeon.co/feed.rss"),
+    ];
+    let catalog = write_feed_catalog(&feeds).unwrap();
+    assert!(catalog.starts_with("[[feed]]\nurl = \"https://www.france24.com/en/rss\"\n"));
+    assert_eq!(parse_feed_catalog(&catalog), Ok(feeds));
+}
@@ -0,0 +1,287 @@
+use crate::cache::{CacheConfig, DiskCache};
+use crate::error::FeedError;
+use bytes::{Bytes, BytesMut};
+use http_cache_reqwest::{Cache, CacheMode, HttpCache, HttpCacheOptions};
+use reqwest::header::{
+    CONTENT_LENGTH, ETAG, HeaderMap, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, RETRY_AFTER,
+    USER_AGENT,
+};
+use reqwest::{Client, Proxy, Request, Response, ResponseBuilderExt, StatusCode, redirect};
+use reqwest_middleware::{ClientBuilder, ClientWithMiddleware, Middleware, Next};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime};
+use task_local_extensions::Extensions;
+
+/// How every request for a feed (or a page to discover feeds in) is made.
+#[derive(Debug, Clone)]
+pub struct HttpConfig {
+    // seconds to wait for a connection to be established
+    pub connect_timeout: u64,
+    // seconds a whole request may take, connecting included, to the last byte of the response
+    pub request_timeout: u64,
+    // largest response body accepted, in bytes
+    pub max_body: usize,
+    // redirects followed before giving up
+    pub max_redirects: usize,
+    // e.g. "http://proxy.internal:3128", for all requests
+    pub proxy: Option<String>,
+    pub cache: CacheConfig,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout: 10,
+            request_timeout: 30,
+            max_body: 10 * 1024 * 1024,
+            max_redirects: 5,
+            proxy: None,
+            cache: CacheConfig::default(),
+        }
+    }
+}
+
+/// A client built once, and shared by every fetch, so that connections are pooled.
+#[derive(Debug, Clone)]
+pub struct HttpClient {
+    client: ClientWithMiddleware,
+    // the same client without the cache, sharing its connections
+    uncached: ClientWithMiddleware,
+    cache: Option<DiskCache>,
+}
+
+/// The `ETag` and `Last-Modified` a server sent with a response, to ask for the same
+/// resource only if it changed since.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Validators {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    pub fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+}
+
+impl From<&HeaderMap> for Validators {
+    fn from(headers: &HeaderMap) -> Self {
+        let value = |name| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        Self {
+            etag: value(ETAG),
+            last_modified: value(LAST_MODIFIED),
+        }
+    }
+}
+
+/// The result of a conditional fetch.
+#[derive(Debug, PartialEq)]
+pub enum Fetched {
+    Modified { body: Bytes, validators: Validators },
+    NotModified,
+}
+
+fn user_agent() -> String {
+    format!(
+        "{}/{} +http://github.com/dpapathanasiou/photojournalism",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION")
+    )
+}
+
+fn retry_after(headers: &HeaderMap) -> Option<u64> {
+    // either a number of seconds, or an http date
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    match value.parse::<u64>() {
+        Ok(secs) => Some(secs),
+        Err(_) => {
+            let at = httpdate::parse_http_date(value).ok()?;
+            Some(
+                at.duration_since(SystemTime::now())
+                    .map(|d| d.as_secs())
+                    .unwrap_or_default(),
+            )
+        }
+    }
+}
+
+impl HttpClient {
+    pub fn new(config: &HttpConfig) -> Result<Self, FeedError> {
+        let mut builder = Client::builder()
+            .user_agent(user_agent())
+            .connect_timeout(Duration::from_secs(config.connect_timeout))
+            .timeout(Duration::from_secs(config.request_timeout))
+            .redirect(redirect::Policy::limited(config.max_redirects));
+        if let Some(proxy) = &config.proxy {
+            builder = builder.proxy(Proxy::all(proxy)?);
+        }
+        let inner = builder.build()?;
+        let mut client = ClientBuilder::new(inner.clone());
+        let cache = config.cache.enabled.then(|| DiskCache::new(&config.cache));
+        if let Some(manager) = &cache {
+            client = client.with(Cache(HttpCache {
+                mode: CacheMode::Default,
+                manager: manager.clone(),
+                options: HttpCacheOptions::default(),
+            }));
+        }
+        // added after the cache, so it runs below it: the cache reads every body it
+        // fetches into memory, before anything above it could stop at the limit
+        let limit = || BodyLimit {
+            max_body: config.max_body,
+        };
+        Ok(Self {
+            client: client.with(limit()).build(),
+            uncached: ClientBuilder::new(inner).with(limit()).build(),
+            cache,
+        })
+    }
+
+    /// The same client without the disk cache, for responses which should not take
+    /// the place of feeds in it (such as images, downloaded only once to be hashed).
+    pub fn without_cache(&self) -> Self {
+        Self {
+            client: self.uncached.clone(),
+            uncached: self.uncached.clone(),
+            cache: None,
+        }
+    }
+
+    /// The disk cache behind this client, unless caching is turned off.
+    pub fn cache(&self) -> Option<&DiskCache> {
+        self.cache.as_ref()
+    }
+
+    /// Fetches the body at `url`, giving up as soon as it exceeds the maximum size.
+    pub async fn get(&self, url: &str) -> Result<Bytes, FeedError> {
+        let response = self
+            .client
+            .get(url)
+            .header(USER_AGENT, user_agent())
+            .send()
+            .await?;
+        self.read(check_status(response)?).await
+    }
+
+    /// Fetches the body at `url` only if it changed since `validators` were recorded,
+    /// sending any `headers` configured for the feed along with the request.
+    pub async fn get_if_modified(
+        &self,
+        url: &str,
+        validators: &Validators,
+        headers: &BTreeMap<String, String>,
+    ) -> Result<Fetched, FeedError> {
+        let mut request = self.client.get(url).header(USER_AGENT, user_agent());
+        for (name, value) in headers {
+            request = request.header(name, value);
+        }
+        if let Some(etag) = &validators.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &validators.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+        let response = request.send().await?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            return Ok(Fetched::NotModified);
+        }
+
+        // when the cache revalidates on our behalf, the 304 comes back as the cached
+        // response, so unchanged validators mean the same thing
+        let response = check_status(response)?;
+        let current = Validators::from(response.headers());
+        if !current.is_empty() && current == *validators {
+            return Ok(Fetched::NotModified);
+        }
+        Ok(Fetched::Modified {
+            body: self.read(response).await?,
+            validators: current,
+        })
+    }
+
+    async fn read(&self, response: Response) -> Result<Bytes, FeedError> {
+        // already limited by `BodyLimit`
+        Ok(response.bytes().await?)
+    }
+}
+
+/// Refuses any response body larger than `max_body` bytes.
+#[derive(Debug)]
+struct BodyLimit {
+    max_body: usize,
+}
+
+impl BodyLimit {
+    async fn read(&self, mut response: Response) -> reqwest_middleware::Result<Bytes> {
+        let too_large = || {
+            reqwest_middleware::Error::middleware(FeedError::TooLarge {
+                limit: self.max_body,
+            })
+        };
+        let length = response
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<usize>().ok());
+        if length.is_some_and(|length| length > self.max_body) {
+            return Err(too_large());
+        }
+
+        // the declared length may be missing (or wrong), so count as the body arrives
+        let mut content = BytesMut::with_capacity(length.unwrap_or_default());
+        while let Some(chunk) = response.chunk().await? {
+            if content.len() + chunk.len() > self.max_body {
+                return Err(too_large());
+            }
+            content.extend_from_slice(&chunk);
+        }
+        Ok(content.freeze())
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for BodyLimit {
+    async fn handle(
+        &self,
+        request: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        let response = next.run(request, extensions).await?;
+        let mut limited = http::Response::builder()
+            .status(response.status())
+            .version(response.version())
+            .url(response.url().clone());
+        if let Some(headers) = limited.headers_mut() {
+            *headers = response.headers().clone();
+        }
+        let body = self.read(response).await?;
+        let limited = limited
+            .body(body)
+            .map_err(reqwest_middleware::Error::middleware)?;
+        Ok(limited.into())
+    }
+}
+
+fn check_status(response: Response) -> Result<Response, FeedError> {
+    let status = response.status();
+    if !status.is_success() {
+        return Err(FeedError::Status {
+            code: status.as_u16(),
+            retry_after: retry_after(response.headers()),
+        });
+    }
+    Ok(response)
+}
+
+#[path = "http_test.rs"]
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,217 @@
+use super::*;
+use crate::cache::CacheConfig;
+use crate::test_server::{reply, serve, serve_nothing};
+use tokio::io::AsyncWriteExt;
+
+// answers with `head` followed by `size` bytes of body
+async fn serve_once(head: &'static str, size: usize) -> String {
+    let address = serve(move |_, socket| async move {
+        reply(socket, [head.as_bytes(), &vec![b'x'; size]].concat()).await;
+    })
+    .await;
+    format!("http://{address}/feed")
+}
+
+// answers with a 304 if the request carries `If-None-Match: "v1"`
+async fn serve_conditionally() -> String {
+    let address = serve(|request, socket| async move {
+        let response = match request.header("if-none-match") {
+            Some("\"v1\"") => "HTTP/1.1 304 Not Modified\r\nETag: \"v1\"\r\n\r\n",
+            _ => "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nContent-Length: 4\r\n\r\nfeed",
+        };
+        reply(socket, response).await;
+    })
+    .await;
+    format!("http://{address}/feed")
+}
+
+// answers with a body which never ends, without a Content-Length
+async fn serve_endlessly() -> String {
+    let address = serve(|_, mut socket| async move {
+        let _ = socket
+            .write_all(b"HTTP/1.1 200 OK\r\nConnection: close\r\n\r\n")
+            .await;
+        while socket.write_all(&[b'x'; 1024]).await.is_ok() {}
+    })
+    .await;
+    format!("http://{address}/feed")
+}
+
+fn client(max_body: usize) -> HttpClient {
+    // without the disk cache, so every request reaches the test server
+    HttpClient::new(&HttpConfig {
+        max_body,
+        cache: CacheConfig {
+            enabled: false,
+            ..CacheConfig::default()
+        },
+        ..HttpConfig::default()
+    })
+    .unwrap()
+}
+
+#[test]
+fn retry_after_accepts_seconds_or_dates() {
+    let mut headers = HeaderMap::new();
+    assert_eq!(retry_after(&headers), None);
+
+    headers.insert(RETRY_AFTER, "120".parse().unwrap());
+    assert_eq!(retry_after(&headers), Some(120));
+
+    // a date in the past means there is no need to wait
+    headers.insert(
+        RETRY_AFTER,
+        "Fri, 24 Nov 2023 17:22:40 GMT".parse().unwrap(),
+    );
+    assert_eq!(retry_after(&headers), Some(0));
+
+    headers.insert(RETRY_AFTER, "soon".parse().unwrap());
+    assert_eq!(retry_after(&headers), None);
+}
+
+#[tokio::test]
+async fn bodies_within_the_limit_are_returned() {
+    let url = serve_once(
+        "HTTP/1.1 200 OK\r\nCache-Control: no-store\r\nContent-Length: 64\r\n\r\n",
+        64,
+    )
+    .await;
+    let body = client(64).get(&url).await.unwrap();
+    assert_eq!(body.len(), 64);
+}
+
+#[tokio::test]
+async fn declared_lengths_over_the_limit_are_refused() {
+    let url = serve_once(
+        "HTTP/1.1 200 OK\r\nCache-Control: no-store\r\nContent-Length: 65\r\n\r\n",
+        65,
+    )
+    .await;
+    assert_eq!(
+        client(64).get(&url).await,
+        Err(FeedError::TooLarge { limit: 64 })
+    );
+}
+
+#[tokio::test]
+async fn undeclared_lengths_are_counted_as_they_arrive() {
+    // no Content-Length, so the body runs until the connection closes
+    let url = serve_once(
+        "HTTP/1.1 200 OK\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+        4096,
+    )
+    .await;
+    assert_eq!(
+        client(1024).get(&url).await,
+        Err(FeedError::TooLarge { limit: 1024 })
+    );
+}
+
+#[tokio::test]
+async fn error_statuses_keep_their_code() {
+    let url = serve_once(
+        "HTTP/1.1 503 Service Unavailable\r\nRetry-After: 30\r\nContent-Length: 0\r\n\r\n",
+        0,
+    )
+    .await;
+    assert_eq!(
+        client(64).get(&url).await,
+        Err(FeedError::Status {
+            code: 503,
+            retry_after: Some(30)
+        })
+    );
+}
+
+#[tokio::test]
+async fn unchanged_feeds_are_not_downloaded_again() {
+    let url = serve_conditionally().await;
+    let first = client(64)
+        .get_if_modified(&url, &Validators::default(), &BTreeMap::new())
+        .await
+        .unwrap();
+    let validators = Validators {
+        etag: Some("\"v1\"".to_string()),
+        last_modified: None,
+    };
+    assert_eq!(
+        first,
+        Fetched::Modified {
+            body: Bytes::from("feed"),
+            validators: validators.clone()
+        }
+    );
+
+    let url = serve_conditionally().await;
+    let second = client(64)
+        .get_if_modified(&url, &validators, &BTreeMap::new())
+        .await
+        .unwrap();
+    assert_eq!(second, Fetched::NotModified);
+}
+
+#[tokio::test]
+async fn responses_with_the_same_validators_are_not_modified() {
+    // as when the cache has already revalidated the response with the server
+    let url = serve_once(
+        "HTTP/1.1 200 OK\r\nLast-Modified: Fri, 24 Nov 2023 17:22:40 GMT\r\nContent-Length: 4\r\n\r\n",
+        4,
+    )
+    .await;
+    let validators = Validators {
+        etag: None,
+        last_modified: Some("Fri, 24 Nov 2023 17:22:40 GMT".to_string()),
+    };
+    assert_eq!(
+        client(64)
+            .get_if_modified(&url, &validators, &BTreeMap::new())
+            .await,
+        Ok(Fetched::NotModified)
+    );
+}
+
+#[tokio::test]
+async fn errors_wrapped_by_the_cache_keep_their_kind() {
+    let url = format!("http://{}/feed", serve_nothing().await);
+    let client = HttpClient::new(&HttpConfig {
+        request_timeout: 1,
+        cache: CacheConfig {
+            dir: std::env::temp_dir()
+                .join(format!("photojournalism-wrapped-{}", std::process::id())),
+            ..CacheConfig::default()
+        },
+        ..HttpConfig::default()
+    })
+    .unwrap();
+    assert!(client.cache().is_some());
+    assert_eq!(client.get(&url).await, Err(FeedError::Timeout));
+}
+
+#[tokio::test]
+async fn the_limit_holds_with_the_cache_on() {
+    // the defaults, cache and all, except for where the cache lives and the limit itself
+    let client = HttpClient::new(&HttpConfig {
+        max_body: 1024,
+        cache: CacheConfig {
+            dir: std::env::temp_dir().join(format!("photojournalism-limit-{}", std::process::id())),
+            ..CacheConfig::default()
+        },
+        ..HttpConfig::default()
+    })
+    .unwrap();
+    assert!(client.cache().is_some());
+
+    let declared = serve_once("HTTP/1.1 200 OK\r\nContent-Length: 4096\r\n\r\n", 4096).await;
+    assert_eq!(
+        client.get(&declared).await,
+        Err(FeedError::TooLarge { limit: 1024 })
+    );
+    // the cache reads bodies whole, so this would never end if the limit sat above it
+    let endless = serve_endlessly().await;
+    assert_eq!(
+        client.get(&endless).await,
+        Err(FeedError::TooLarge { limit: 1024 })
+    );
+    let within = serve_once("HTTP/1.1 200 OK\r\nContent-Length: 1024\r\n\r\n", 1024).await;
+    assert_eq!(client.get(&within).await.map(|body| body.len()), Ok(1024));
+}
@@ -0,0 +1,293 @@
+use crate::canonical::{canonicalize, canonicalize_media};
+use crate::parser::NewsPhoto;
+use crate::phash::{ImageHashes, distance};
+use crate::shuffler::randomize;
+use crate::store::{Store, StoreResult};
+use arc_swap::ArcSwap;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, OnceLock};
+
+// the shuffled order is kept for the seeds of most clients (see server::get_next);
+// any other seed has it worked out on every request
+const CACHED_SEEDS: usize = 512;
+
+// between the credits of a photo merged from duplicates
+const CREDITS: &str = "; ";
+
+/// Every photo in the store, flattened (and with duplicates across feeds merged) once
+/// when published, and never changed after.
+pub struct PhotoIndex {
+    photos: Vec<NewsPhoto>,
+    feeds: usize,
+    orders: Box<[OnceLock<Vec<usize>>]>,
+}
+
+impl Default for PhotoIndex {
+    fn default() -> Self {
+        Self::new(Vec::new(), 0)
+    }
+}
+
+impl PhotoIndex {
+    pub fn new(photos: Vec<NewsPhoto>, feeds: usize) -> Self {
+        Self {
+            photos,
+            feeds,
+            orders: (0..CACHED_SEEDS).map(|_| OnceLock::new()).collect(),
+        }
+    }
+
+    /// Flattens the photos of every feed in `store`, merging those which share a canonical
+    /// image or story url (the same wire photo run by several outlets, or the same story
+    /// in several sections of one) into one, which lists every feed it appeared in.
+    ///
+    /// With `hashes`, photos whose images look alike (the same frame under another url,
+    /// crop or resolution) are merged the same way.
+    pub fn build(store: &dyn Store, hashes: Option<&ImageHashes>) -> StoreResult<Self> {
+        Ok(Self::from_feeds(store.photos_by_feed()?, hashes))
+    }
+
+    /// Same as `build`, from the photos of every feed as read from the store.
+    pub fn from_feeds(
+        by_feed: Vec<(String, Vec<NewsPhoto>)>,
+        hashes: Option<&ImageHashes>,
+    ) -> Self {
+        let feeds = by_feed.len();
+        let mut photos: Vec<NewsPhoto> = Vec::new();
+        let mut by_url: HashMap<String, usize> = HashMap::new();
+        for (feed, feed_photos) in by_feed {
+            for mut photo in feed_photos {
+                photo.feeds = vec![feed.clone()];
+                let urls = [
+                    canonicalize_media(&photo.image_url),
+                    canonicalize(&photo.story_url),
+                ]
+                .into_iter()
+                .filter(|url| !url.is_empty())
+                .collect::<Vec<_>>();
+                let position = match urls.iter().find_map(|url| by_url.get(url)) {
+                    Some(&position) => {
+                        combine(&mut photos[position], photo);
+                        position
+                    }
+                    None => {
+                        photos.push(photo);
+                        photos.len() - 1
+                    }
+                };
+                for url in urls {
+                    by_url.entry(url).or_insert(position);
+                }
+            }
+        }
+        if let Some(hashes) = hashes {
+            photos = cluster(photos, hashes);
+        }
+        Self::new(photos, feeds)
+    }
+
+    /// The number of feeds, and of photos.
+    pub fn counts(&self) -> (usize, usize) {
+        (self.feeds, self.photos.len())
+    }
+
+    /// Up to `size` photos for which `keep` holds, from `start` on in the order shuffled by `seed`.
+    pub fn page(
+        &self,
+        seed: u64,
+        start: usize,
+        size: usize,
+        keep: impl Fn(&NewsPhoto) -> bool,
+    ) -> Vec<&NewsPhoto> {
+        let uncached;
+        let order = match self.orders.get(seed as usize) {
+            Some(order) => order.get_or_init(|| randomize(seed, self.photos.len())),
+            None => {
+                uncached = randomize(seed, self.photos.len());
+                &uncached
+            }
+        };
+        order
+            .iter()
+            .map(|i| &self.photos[*i])
+            .filter(|photo| keep(photo))
+            .skip(start)
+            .take(size)
+            .collect()
+    }
+}
+
+/// Merges every photo into the first one before it whose image is within the maximum
+/// distance of its own; photos whose image has no hash (yet) are left as they are.
+fn cluster(photos: Vec<NewsPhoto>, hashes: &ImageHashes) -> Vec<NewsPhoto> {
+    let bands = bands(hashes.max_distance());
+    let mut clusters: Vec<(Option<u64>, NewsPhoto)> = Vec::new();
+    // the clusters with a hash, by the value of each of its bands, so a photo is only
+    // compared with the few clusters sharing a band with it rather than with every one
+    let mut buckets: HashMap<(usize, u64), Vec<usize>> = HashMap::new();
+    for photo in photos {
+        let hash = hashes.get(&photo.image_url);
+        let similar = hash.and_then(|hash| {
+            band_values(&bands, hash)
+                .filter_map(|band| buckets.get(&band))
+                .flatten()
+                .copied()
+                .filter(|i| {
+                    clusters[*i]
+                        .0
+                        .is_some_and(|other| distance(hash, other) <= hashes.max_distance())
+                })
+                .min()
+        });
+        match similar {
+            Some(i) => combine(&mut clusters[i].1, photo),
+            None => {
+                if let Some(hash) = hash {
+                    for band in band_values(&bands, hash) {
+                        buckets.entry(band).or_default().push(clusters.len());
+                    }
+                }
+                clusters.push((hash, photo));
+            }
+        }
+    }
+    clusters.into_iter().map(|(_, photo)| photo).collect()
+}
+
+// splits the 64 bits of a hash into one more band than the maximum distance, as (shift,
+// mask) pairs: two hashes that close must then agree on every bit of at least one band
+fn bands(max_distance: u32) -> Vec<(u32, u64)> {
+    if max_distance >= u64::BITS {
+        // every hash is close enough to every other, so they all share one empty band
+        return vec![(0, 0)];
+    }
+    let count = max_distance + 1;
+    (0..count)
+        .map(|band| {
+            let (start, end) = (band * u64::BITS / count, (band + 1) * u64::BITS / count);
+            (start, u64::MAX >> (u64::BITS - (end - start)))
+        })
+        .collect()
+}
+
+fn band_values(bands: &[(u32, u64)], hash: u64) -> impl Iterator<Item = (usize, u64)> + '_ {
+    bands
+        .iter()
+        .enumerate()
+        .map(move |(band, (shift, mask))| (band, (hash >> shift) & mask))
+}
+
+// how much a duplicate says about the photo, so the one which says the most is kept
+fn quality(photo: &NewsPhoto) -> (usize, usize) {
+    let fields = [
+        photo.description.is_some(),
+        photo.credit.is_some(),
+        photo.published.is_some(),
+    ];
+    (
+        fields.into_iter().filter(|set| *set).count(),
+        photo
+            .description
+            .as_ref()
+            .map(String::len)
+            .unwrap_or_default(),
+    )
+}
+
+/// Folds a duplicate into the photo already in the index: the better described of the two
+/// is kept, with anything it lacks taken from the other, over the times both were seen.
+fn combine(kept: &mut NewsPhoto, mut other: NewsPhoto) {
+    if quality(&other) > quality(kept) {
+        std::mem::swap(kept, &mut other);
+    }
+    for feed in other.feeds {
+        if !kept.feeds.contains(&feed) {
+            kept.feeds.push(feed);
+        }
+    }
+    // along with where each field came from, for /api/next?debug=true
+    if kept.description.is_none() {
+        kept.description = other.description;
+        kept.provenance.description = other.provenance.description;
+    }
+    // the same frame is often credited differently by each outlet, so every credit is kept
+    match (&mut kept.credit, other.credit) {
+        (Some(credit), Some(others)) => {
+            for added in others.split(CREDITS) {
+                if !credit.split(CREDITS).any(|known| known == added) {
+                    credit.push_str(CREDITS);
+                    credit.push_str(added);
+                }
+            }
+        }
+        (None, others) => {
+            kept.credit = others;
+            kept.provenance.credit = other.provenance.credit;
+        }
+        (Some(_), None) => {}
+    }
+    if kept.video_url.is_none() && other.video_url.is_some() {
+        kept.video_url = other.video_url;
+        kept.media = other.media;
+        kept.provenance.video_url = other.provenance.video_url;
+    }
+    kept.first_seen = match (kept.first_seen, other.first_seen) {
+        (0, seen) | (seen, 0) => seen,
+        (a, b) => a.min(b),
+    };
+    kept.last_seen = kept.last_seen.max(other.last_seen);
+    kept.published = kept.published.into_iter().chain(other.published).min();
+    // still served as long as any feed would serve it
+    kept.expires_at = kept.expires_at.zip(other.expires_at).map(|(a, b)| a.max(b));
+}
+
+/// The current `PhotoIndex`, which readers load without taking any lock, and which
+/// the loader replaces whole every time the store changes.
+#[derive(Clone, Default)]
+pub struct SharedIndex {
+    current: Arc<ArcSwap<PhotoIndex>>,
+    // only one index is built at a time, so an older one never replaces a newer one
+    publishing: Arc<Mutex<()>>,
+    // for merging near duplicates, when turned on
+    hashes: Option<ImageHashes>,
+}
+
+impl SharedIndex {
+    /// An index which also merges photos whose images look alike, going by `hashes`.
+    pub fn with_image_hashes(hashes: ImageHashes) -> Self {
+        Self {
+            hashes: Some(hashes),
+            ..Self::default()
+        }
+    }
+
+    pub fn image_hashes(&self) -> Option<&ImageHashes> {
+        self.hashes.as_ref()
+    }
+
+    pub fn load(&self) -> Arc<PhotoIndex> {
+        self.current.load_full()
+    }
+
+    /// Rebuilds the index from everything in `store`, and makes it the current one.
+    pub fn publish(&self, store: &dyn Store) -> StoreResult<()> {
+        let _publishing = self.publishing.lock()?;
+        let by_feed = store.photos_by_feed()?;
+        if let Some(hashes) = &self.hashes {
+            // the images of photos no longer in any feed need not be remembered, unlike those
+            // of duplicates merged away, which would otherwise be downloaded with every fetch
+            let current: HashSet<&str> = by_feed
+                .iter()
+                .flat_map(|(_, photos)| photos.iter().map(|photo| photo.image_url.as_str()))
+                .collect();
+            hashes.retain(|image_url| current.contains(image_url));
+        }
+        let index = PhotoIndex::from_feeds(by_feed, self.hashes.as_ref());
+        self.current.store(Arc::new(index));
+        Ok(())
+    }
+}
+
+#[path = "index_test.rs"]
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,267 @@
+use super::*;
+use crate::parser::MediaKind;
+use crate::phash::{PhashConfig, dhash};
+use crate::store::MemoryStore;
+use std::collections::HashMap;
+
+fn photos(feed: &str, count: usize) -> Vec<NewsPhoto> {
+    (0..count)
+        .map(|i| NewsPhoto {
+            image_url: format!("{feed}/{i}.jpg"),
+            story_url: format!("{feed}/{i}"),
+            ..NewsPhoto::new()
+        })
+        .collect()
+}
+
+#[test]
+fn pages_cover_every_photo_once_in_a_stable_order() {
+    let store = MemoryStore::from(HashMap::from([
+        ("https://aeon.co".to_string(), photos("https://aeon.co", 7)),
+        (
+            "https://www.france24.com".to_string(),
+            photos("https://www.france24.com", 6),
+        ),
+    ]));
+    let index = PhotoIndex::build(&store, None).unwrap();
+    assert_eq!(index.counts(), (2, 13));
+
+    // a seed past the cached ones is shuffled the same way, only on every request
+    for seed in [7, CACHED_SEEDS as u64 + 7] {
+        let mut seen: Vec<String> = Vec::new();
+        for start in (0..13).step_by(5) {
+            let stories = |page: Vec<&NewsPhoto>| -> Vec<String> {
+                page.iter().map(|photo| photo.story_url.clone()).collect()
+            };
+            let page = stories(index.page(seed, start, 5, |_| true));
+            assert_eq!(page, stories(index.page(seed, start, 5, |_| true)));
+            seen.extend(page);
+        }
+        assert_eq!(seen.len(), 13);
+        seen.sort();
+        seen.dedup();
+        assert_eq!(seen.len(), 13);
+    }
+    assert!(index.page(7, 13, 5, |_| true).is_empty());
+}
+
+#[test]
+fn pages_only_count_the_photos_kept() {
+    let mut feed = photos("https://www.france24.com", 6);
+    for photo in feed.iter_mut().step_by(2) {
+        photo.media = MediaKind::Video;
+    }
+    let index = PhotoIndex::new(feed, 1);
+    let videos = |photo: &NewsPhoto| photo.media == MediaKind::Video;
+    assert_eq!(index.page(3, 0, 2, videos).len(), 2);
+    assert_eq!(index.page(3, 2, 2, videos).len(), 1);
+    assert!(index.page(3, 3, 2, videos).is_empty());
+}
+
+#[test]
+fn readers_keep_the_index_they_loaded_until_they_load_again() {
+    let store = MemoryStore::default();
+    let shared = SharedIndex::default();
+    assert_eq!(shared.load().counts(), (0, 0));
+
+    store
+        .upsert_feed("https://aeon.co", photos("https://aeon.co", 3))
+        .unwrap();
+    let before = shared.load();
+    shared.publish(&store).unwrap();
+    assert_eq!(before.counts(), (0, 0));
+    assert_eq!(shared.load().counts(), (1, 3));
+}
+
+#[test]
+fn duplicates_across_feeds_are_served_once() {
+    let homepage = "https://rss.nytimes.com/services/xml/rss/nyt/HomePage.xml";
+    let world = "https://rss.nytimes.com/services/xml/rss/nyt/World.xml";
+    let france24 = "https://www.france24.com/en/rss";
+    let story =
+        "https://www.nytimes.com/2023/11/23/world/europe/finland-russia-border-migrants.html";
+    let store = MemoryStore::default();
+    store
+        .upsert_feed(
+            homepage,
+            vec![NewsPhoto {
+                image_url: "https://static01.nyt.com/images/finland.jpg".to_string(),
+                story_url: format!("{story}?smid=url-share"),
+                first_seen: 1700845200,
+                last_seen: 1700848800,
+                expires_at: Some(1700900000),
+                ..NewsPhoto::new()
+            }],
+        )
+        .unwrap();
+    store
+        .upsert_feed(
+            world,
+            vec![NewsPhoto {
+                image_url: "https://static01.nyt.com/images/finland-large.jpg".to_string(),
+                story_url: story.to_string(),
+                description: Some("Finnish border guards escorting migrants".to_string()),
+                first_seen: 1700841600,
+                last_seen: 1700845200,
+                published: Some(1700838000),
+                expires_at: Some(1700950000),
+                ..NewsPhoto::new()
+            }],
+        )
+        .unwrap();
+    // the same wire photo, run with another story and a credit
+    store
+        .upsert_feed(
+            france24,
+            vec![NewsPhoto {
+                image_url: "https://static01.nyt.com/images/finland-large.jpg".to_string(),
+                story_url: "https://www.france24.com/en/europe/20231123-finland".to_string(),
+                credit: Some("Jussi Nukari/Lehtikuva, via Associated Press".to_string()),
+                expires_at: Some(1700870000),
+                ..NewsPhoto::new()
+            }],
+        )
+        .unwrap();
+
+    let index = PhotoIndex::build(&store, None).unwrap();
+    assert_eq!(index.counts(), (3, 1));
+    let photo = index.page(1, 0, 10, |_| true)[0];
+    // the best described of the duplicates, with the credit only another one had
+    assert_eq!(photo.story_url, story);
+    assert_eq!(
+        photo.description.as_deref(),
+        Some("Finnish border guards escorting migrants")
+    );
+    assert_eq!(
+        photo.credit.as_deref(),
+        Some("Jussi Nukari/Lehtikuva, via Associated Press")
+    );
+    assert_eq!(photo.feeds, vec![world, homepage, france24]);
+    assert_eq!(photo.first_seen, 1700841600);
+    assert_eq!(photo.last_seen, 1700848800);
+    assert_eq!(photo.published, Some(1700838000));
+    assert_eq!(photo.expires_at, Some(1700950000));
+}
+
+#[test]
+fn near_duplicate_images_are_served_once_with_every_credit() {
+    let hashes = ImageHashes::new(PhashConfig::default().max_distance);
+    let store = MemoryStore::default();
+    // the same frame as run by three outlets, at other sizes and crops, and another photo
+    for (outlet, image, credit) in [
+        ("apnews.com", "wire.jpg", "Jussi Nukari/Lehtikuva via AP"),
+        (
+            "reuters.com",
+            "wire-small.jpg",
+            "Lehtikuva/Jussi Nukari via Reuters",
+        ),
+        (
+            "france24.com",
+            "wire-cropped.jpg",
+            "Jussi Nukari/Lehtikuva via AP",
+        ),
+        ("aeon.co", "other.jpg", "Aeon"),
+    ] {
+        let image_url = format!("https://{outlet}/images/{image}");
+        let bytes = std::fs::read(format!("tests/fixtures/images/{image}")).unwrap();
+        hashes.insert(&image_url, dhash(&bytes).ok());
+        store
+            .upsert_feed(
+                &format!("https://{outlet}/rss"),
+                vec![NewsPhoto {
+                    image_url,
+                    story_url: format!("https://{outlet}/story"),
+                    credit: Some(credit.to_string()),
+                    ..NewsPhoto::new()
+                }],
+            )
+            .unwrap();
+    }
+
+    assert_eq!(PhotoIndex::build(&store, None).unwrap().counts(), (4, 4));
+    let index = PhotoIndex::build(&store, Some(&hashes)).unwrap();
+    assert_eq!(index.counts(), (4, 2));
+    let mut photos = index.page(1, 0, 10, |_| true);
+    photos.sort_by_key(|photo| photo.feeds.len());
+    assert_eq!(photos[0].credit.as_deref(), Some("Aeon"));
+    assert_eq!(
+        photos[1].credit.as_deref(),
+        Some("Jussi Nukari/Lehtikuva via AP; Lehtikuva/Jussi Nukari via Reuters")
+    );
+    assert_eq!(photos[1].feeds.len(), 3);
+}
+
+#[test]
+fn hashes_are_kept_for_every_stored_image_merged_away_or_not() {
+    let hashes = ImageHashes::new(PhashConfig::default().max_distance);
+    let store = MemoryStore::default();
+    // the same story run by two outlets with their own images, so one image is merged away
+    for outlet in ["apnews.com", "reuters.com"] {
+        let image_url = format!("https://{outlet}/images/wire.jpg");
+        hashes.insert(&image_url, Some(0));
+        store
+            .upsert_feed(
+                &format!("https://{outlet}/rss"),
+                vec![NewsPhoto {
+                    image_url,
+                    story_url: "https://apnews.com/story".to_string(),
+                    ..NewsPhoto::new()
+                }],
+            )
+            .unwrap();
+    }
+    hashes.insert("https://aeon.co/images/gone.jpg", Some(0));
+
+    let index = SharedIndex::with_image_hashes(hashes.clone());
+    index.publish(&store).unwrap();
+    assert_eq!(index.load().counts(), (2, 1));
+    assert!(hashes.get("https://apnews.com/images/wire.jpg").is_some());
+    assert!(hashes.get("https://reuters.com/images/wire.jpg").is_some());
+    assert!(hashes.get("https://aeon.co/images/gone.jpg").is_none());
+}
+
+#[test]
+fn images_within_the_distance_are_merged_whichever_bits_differ() {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    let mut rng = StdRng::seed_from_u64(7);
+    for max_distance in [0, 1, 6, 10, 63, 64] {
+        let hashes = ImageHashes::new(max_distance);
+        // a few originals, each with variants a few bits off, anywhere in the hash
+        let mut photos = Vec::new();
+        for original in 0..20 {
+            let hash: u64 = rng.r#gen();
+            for variant in 0..5 {
+                let mut flipped = hash;
+                for _ in 0..rng.gen_range(0..=max_distance.min(12) + 2) {
+                    flipped ^= 1 << rng.gen_range(0..64);
+                }
+                let image_url = format!("https://example.com/{original}/{variant}.jpg");
+                hashes.insert(&image_url, Some(flipped));
+                photos.push(NewsPhoto {
+                    image_url,
+                    story_url: format!("https://example.com/{original}/{variant}"),
+                    ..NewsPhoto::new()
+                });
+            }
+        }
+
+        // as when every photo was compared with every cluster kept before it
+        let mut expected: Vec<u64> = Vec::new();
+        for photo in &photos {
+            let hash = hashes.get(&photo.image_url).unwrap();
+            if !expected
+                .iter()
+                .any(|kept| distance(hash, *kept) <= max_distance)
+            {
+                expected.push(hash);
+            }
+        }
+        let clustered: Vec<u64> = cluster(photos, &hashes)
+            .iter()
+            .map(|photo| hashes.get(&photo.image_url).unwrap())
+            .collect();
+        assert_eq!(clustered, expected, "max distance {max_distance}");
+    }
+}
@@ -1,4 +1,20 @@
+pub mod cache;
+pub mod canonical;
+pub mod cli;
+pub mod discovery;
+pub mod error;
+pub mod feeds;
+pub mod http;
+pub mod index;
 pub mod loader;
+pub mod opml;
 pub mod parser;
+pub mod phash;
 pub mod server;
 pub mod shuffler;
+pub mod snapshot;
+pub mod sqlite;
+pub mod store;
+
+#[cfg(test)]
+mod test_server;
@@ -1,36 +1,697 @@
-use crate::parser::{NewsPhoto, get_photos_from_feed};
-use std::collections::HashMap;
+use crate::error::FeedError;
+use crate::feeds::Feed;
+use crate::http::{HttpClient, Validators};
+use crate::index::SharedIndex;
+use crate::parser::{ExtractionReport, NewsPhoto, Rejection, get_photos_if_modified};
+use crate::store::{Store, StoreError, StoreResult};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
-use tokio::time;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::{Semaphore, mpsc, watch};
+use tokio::task::{self, JoinSet};
+use tokio::time::{self, MissedTickBehavior};
+use url::Url;
 
-pub type FeedDb = Arc<Mutex<HashMap<String, Vec<NewsPhoto>>>>;
+pub type FeedDb = Arc<dyn Store>;
 
-async fn fetch(feeds: Vec<String>, db: FeedDb) {
-    for feed in feeds {
-        let photos = get_photos_from_feed(&feed).await;
-        match db.lock() {
-            Ok(mut hash) => {
-                hash.insert(feed.to_string(), photos.clone());
+/// Whether a feed is fetched as usual (closed), has failed so often that it
+/// is only probed occasionally (open), or is being probed right now (half open).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Breaker {
+    #[default]
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FeedStatus {
+    // seconds since the unix epoch
+    pub last_fetched: u64,
+    pub report: ExtractionReport,
+    pub last_error: Option<FeedError>,
+    // number of failed fetches, by FeedError::kind()
+    pub errors: BTreeMap<String, u64>,
+    // seconds between fetches of this feed
+    pub interval: u64,
+    // seconds since the unix epoch
+    pub next_fetch: u64,
+    // number of fetches in a row which failed
+    pub failures: u32,
+    pub breaker: Breaker,
+    // sent with the next fetch, so an unchanged feed is neither downloaded nor parsed again
+    #[serde(default, skip_serializing_if = "Validators::is_empty")]
+    pub validators: Validators,
+    // fetches which found the feed unchanged (hits) or changed (misses)
+    pub hits: u64,
+    pub misses: u64,
+    // seconds since the unix epoch when the feed was taken out of the list, and its photos dropped
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub evicted: Option<u64>,
+}
+
+impl FeedStatus {
+    fn record(&mut self, result: Result<ExtractionReport, FeedError>, config: &LoaderConfig) {
+        self.last_fetched = now();
+        match result {
+            Ok(report) => {
+                self.report = report;
+                self.misses += 1;
+                self.succeeded();
             }
-            _ => {
-                log::error!("rss fetch: could not obtain FeedDb lock")
+            Err(err) => {
+                *self.errors.entry(err.kind().to_string()).or_insert(0) += 1;
+                // retrying soon would not fix an error like a 404, so only probe from now on
+                let permanent = !err.is_transient();
+                self.last_error = Some(err);
+                self.failures += 1;
+                if permanent || self.failures >= config.failure_threshold.max(1) {
+                    self.breaker = Breaker::Open;
+                }
             }
         }
     }
+
+    fn record_not_modified(&mut self) {
+        // the last report still describes the feed
+        self.last_fetched = now();
+        self.hits += 1;
+        self.succeeded();
+    }
+
+    fn succeeded(&mut self) {
+        self.last_error = None;
+        self.failures = 0;
+        self.breaker = Breaker::Closed;
+    }
+
+    /// Seconds until the next fetch: the regular interval after a success, growing
+    /// exponentially with each failure in a row, and the probe interval once the breaker
+    /// is open, but never sooner than the server asked for with `Retry-After` (up to the probe interval,
+    /// as a server could ask for anything).
+    fn delay(&self, interval: u64, config: &LoaderConfig) -> u64 {
+        let delay = match (self.failures, self.breaker) {
+            (0, _) => interval,
+            (_, Breaker::Open) => config.probe_interval,
+            (failures, _) => interval
+                .saturating_mul(1 << failures.min(16))
+                .min(config.max_backoff.max(interval)),
+        };
+        let retry_after = self.last_error.as_ref().and_then(|err| err.retry_after());
+        delay.max(retry_after.unwrap_or_default().min(config.probe_interval))
+    }
+
+    fn schedule(&mut self, interval: u64, config: &LoaderConfig) {
+        // never fetch during the hours or days the feed asks to be skipped
+        let delay = self.delay(interval, config);
+        self.interval = interval;
+        self.next_fetch = self.report.refresh.skip(
+            now()
+                .saturating_add(delay)
+                .saturating_add(config.jitter_for(delay)),
+        );
+    }
 }
 
-pub async fn background(feeds: Vec<String>, db: FeedDb, interval: u64) {
-    // load the FeedDb in the background, once at the given internal (in seconds)
-    let mut interval = time::interval(Duration::from_secs(interval));
+pub type StatusDb = Arc<Mutex<HashMap<String, FeedStatus>>>;
 
-    loop {
-        let db = db.clone();
-        let feeds = feeds.clone();
-        tokio::spawn(async move {
-            fetch(feeds, db).await;
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// How the loader goes about fetching the list of feeds.
+#[derive(Debug, Clone)]
+pub struct LoaderConfig {
+    // seconds between fetches of a feed, unless the list of feeds or the feed itself says otherwise
+    pub interval: u64,
+    // bounds (in seconds) on the interval of every feed
+    pub min_interval: u64,
+    pub max_interval: u64,
+    // maximum number of feeds fetched at the same time
+    pub concurrency: usize,
+    // maximum number of feeds fetched at the same time from any one host
+    pub per_host: usize,
+    // maximum random delay (in seconds) added to the next fetch of each feed
+    pub jitter: u64,
+    // failures in a row after which a feed's breaker opens
+    pub failure_threshold: u32,
+    // longest delay (in seconds) between retries of a failing feed, before its breaker opens
+    pub max_backoff: u64,
+    // seconds between probes of a feed whose breaker is open
+    pub probe_interval: u64,
+    // seconds a photo is kept after it drops out of its feed
+    pub retention: u64,
+    // seconds after which a photo is too old to serve, 0 for no limit, unless the feed says otherwise
+    pub max_age: u64,
+    // seconds to wait at shutdown for the fetches in flight to finish
+    pub shutdown_timeout: u64,
+    // maximum number of images downloaded at the same time, with perceptual hashing on
+    pub hash_concurrency: usize,
+}
+
+impl Default for LoaderConfig {
+    fn default() -> Self {
+        Self {
+            interval: 3600,
+            min_interval: 300,
+            max_interval: 86400,
+            concurrency: 8,
+            per_host: 2,
+            jitter: 30,
+            failure_threshold: 5,
+            max_backoff: 21600,
+            probe_interval: 86400,
+            retention: 86400,
+            max_age: 0,
+            shutdown_timeout: 30,
+            hash_concurrency: 4,
+        }
+    }
+}
+
+impl LoaderConfig {
+    /// Seconds between fetches of `feed`: its own setting in the list of feeds if
+    /// there is one, or else what its channel suggests, or else the global interval.
+    pub fn interval_for(&self, feed: &Feed, report: &ExtractionReport) -> u64 {
+        feed.interval
+            .or(report.refresh.interval())
+            .unwrap_or(self.interval)
+            .clamp(self.min_interval, self.max_interval.max(self.min_interval))
+    }
+
+    /// Seconds after which photos from `feed` are too old to serve, if there is a limit.
+    pub fn max_age_for(&self, feed: &Feed) -> Option<u64> {
+        match feed.max_age.unwrap_or(self.max_age) {
+            0 => None,
+            max_age => Some(max_age),
+        }
+    }
+
+    fn jitter_for(&self, interval: u64) -> u64 {
+        // keep the jitter well inside the interval, so fetches cannot drift into each other
+        rand::thread_rng().gen_range(0..=self.jitter.min(interval / 2))
+    }
+}
+
+fn host(feed: &str) -> String {
+    match Url::parse(feed) {
+        Ok(url) => url.host_str().unwrap_or_default().to_string(),
+        Err(_) => feed.to_string(),
+    }
+}
+
+type InFlight = Arc<Mutex<HashSet<String>>>;
+
+/// Marks a feed as being fetched, for as long as the guard is held.
+struct FetchGuard {
+    feed: String,
+    in_flight: InFlight,
+}
+
+impl FetchGuard {
+    fn claim(feed: &str, in_flight: &InFlight) -> Option<Self> {
+        let claimed = match in_flight.lock() {
+            Ok(mut feeds) => feeds.insert(feed.to_string()),
+            Err(_) => false,
+        };
+        claimed.then(|| Self {
+            feed: feed.to_string(),
+            in_flight: in_flight.clone(),
+        })
+    }
+}
+
+impl Drop for FetchGuard {
+    fn drop(&mut self) {
+        if let Ok(mut feeds) = self.in_flight.lock() {
+            feeds.remove(&self.feed);
+        }
+    }
+}
+
+/// Merges what a feed holds now into the photos it held before: photos still in the feed
+/// keep their `first_seen`, new ones start at `at`, and those gone from the feed are
+/// kept until `retention` seconds after they were last seen.
+fn merge(known: Vec<NewsPhoto>, fresh: Vec<NewsPhoto>, at: u64, retention: u64) -> Vec<NewsPhoto> {
+    let mut first_seen: HashMap<String, u64> = known
+        .iter()
+        .map(|photo| (photo.identity().to_string(), photo.first_seen))
+        .collect();
+    let mut merged: Vec<NewsPhoto> = Vec::with_capacity(fresh.len());
+    let mut current: HashSet<String> = HashSet::new();
+    for mut photo in fresh {
+        // the same story listed twice counts once
+        if !current.insert(photo.identity().to_string()) {
+            continue;
+        }
+        photo.first_seen = first_seen.remove(photo.identity()).unwrap_or(at);
+        photo.last_seen = at;
+        merged.push(photo);
+    }
+    merged.extend(known.into_iter().filter(|photo| {
+        !current.contains(photo.identity()) && at.saturating_sub(photo.last_seen) <= retention
+    }));
+    merged
+}
+
+/// Dates each photo's expiry by its publication (or else by when it was first seen),
+/// and drops those already past it once they are gone from the feed, returning how many
+/// are past it but still in the feed. Those are kept (though never served), so that
+/// the next fetch does not take them for new photos, seen for the first time.
+fn expire(photos: &mut Vec<NewsPhoto>, max_age: Option<u64>, at: u64) -> usize {
+    let mut expired = 0;
+    photos.retain_mut(|photo| {
+        photo.expires_at =
+            max_age.map(|max_age| photo.published.unwrap_or(photo.first_seen) + max_age);
+        if !photo.expired(at) {
+            return true;
+        }
+        if photo.last_seen == at {
+            expired += 1;
+            return true;
+        }
+        false
+    });
+    expired
+}
+
+/// For a feed which has not changed: every photo last seen in it is still there.
+fn still_seen(mut known: Vec<NewsPhoto>, at: u64, retention: u64) -> Vec<NewsPhoto> {
+    let latest = known
+        .iter()
+        .map(|photo| photo.last_seen)
+        .max()
+        .unwrap_or_default();
+    for photo in known.iter_mut().filter(|photo| photo.last_seen == latest) {
+        photo.last_seen = at;
+    }
+    known.retain(|photo| at.saturating_sub(photo.last_seen) <= retention);
+    known
+}
+
+/// Runs `work` against the store on a thread set aside for blocking, since a store
+/// (such as SQLite) may block, which the async workers must never do.
+async fn blocking<T: Send + 'static>(
+    db: &FeedDb,
+    work: impl FnOnce(&FeedDb) -> StoreResult<T> + Send + 'static,
+) -> StoreResult<T> {
+    let db = db.clone();
+    task::spawn_blocking(move || work(&db))
+        .await
+        .map_err(|err| StoreError::Backend {
+            message: err.to_string(),
+        })?
+}
+
+async fn fetch_feed(
+    client: &HttpClient,
+    feed: &Feed,
+    db: &FeedDb,
+    index: &SharedIndex,
+    status: &StatusDb,
+    config: &LoaderConfig,
+    to_hash: &mpsc::UnboundedSender<String>,
+) {
+    if let Ok(mut hash) = status.lock()
+        && let Some(feed_status) = hash.get_mut(&feed.url)
+        && feed_status.breaker == Breaker::Open
+    {
+        log::info!("rss fetch: probing '{}', whose breaker is open", feed.url);
+        feed_status.breaker = Breaker::HalfOpen;
+    }
+
+    let validators = match status.lock() {
+        Ok(hash) => hash
+            .get(&feed.url)
+            .map(|feed_status| feed_status.validators.clone())
+            .unwrap_or_default(),
+        _ => Validators::default(),
+    };
+
+    let started = Instant::now();
+    // on failure, keep serving whatever the feed produced last time, and the same
+    // when it has not changed since
+    let result = match get_photos_if_modified(client, feed, &validators).await {
+        Ok(Some((photos, mut report, validators))) => {
+            let at = now();
+            let url = feed.url.clone();
+            let (max_age, retention) = (config.max_age_for(feed), config.retention);
+            let stored = blocking(db, move |db| {
+                let mut known = merge(db.feed_photos(&url)?, photos, at, retention);
+                let expired = expire(&mut known, max_age, at);
+                db.upsert_feed(&url, known)?;
+                Ok(expired)
+            })
+            .await;
+            match stored {
+                Ok(expired) => {
+                    // too old to serve counts as rejected, so feeds full of old items stand out
+                    for _ in 0..expired {
+                        report.reject(Rejection::Expired);
+                    }
+                    report.accepted = report.accepted.saturating_sub(expired);
+                }
+                Err(err) => {
+                    log::error!(
+                        "rss fetch: could not store the photos of '{}': {err}",
+                        feed.url
+                    )
+                }
+            }
+            if report.accepted < report.items {
+                log::debug!("rss fetch: '{}' {:?}", feed.url, report);
+            }
+            Ok(Some((report, validators)))
+        }
+        Ok(None) => {
+            log::debug!("rss fetch: '{}' not modified", feed.url);
+            let at = now();
+            let url = feed.url.clone();
+            let (max_age, retention) = (config.max_age_for(feed), config.retention);
+            let stored = blocking(db, move |db| {
+                let mut known = still_seen(db.feed_photos(&url)?, at, retention);
+                expire(&mut known, max_age, at);
+                db.upsert_feed(&url, known)
+            })
+            .await;
+            if let Err(err) = stored {
+                log::error!(
+                    "rss fetch: could not store the photos of '{}': {err}",
+                    feed.url
+                )
+            }
+            Ok(None)
+        }
+        Err(err) => Err(err),
+    };
+    if result.is_ok() {
+        publish(index, db).await;
+        // near duplicates among the new photos are only merged once their images are hashed
+        if index.image_hashes().is_some() {
+            let _ = to_hash.send(feed.url.clone());
+        }
+    }
+    match status.lock() {
+        Ok(mut hash) => {
+            let feed_status = hash.entry(feed.url.to_string()).or_default();
+            let was_open = feed_status.breaker != Breaker::Closed;
+            match result {
+                Ok(Some((report, validators))) => {
+                    feed_status.validators = validators;
+                    feed_status.record(Ok(report), config);
+                }
+                Ok(None) => feed_status.record_not_modified(),
+                Err(err) => feed_status.record(Err(err), config),
+            }
+            let interval = config.interval_for(feed, &feed_status.report);
+            feed_status.schedule(interval, config);
+
+            let retry_in = feed_status.next_fetch.saturating_sub(now());
+            if let Some(err) = &feed_status.last_error {
+                log::error!(
+                    "rss fetch: could not access '{}' ({}, {} in a row, breaker {:?}, next try in {retry_in}s): {err}",
+                    feed.url,
+                    err.kind(),
+                    feed_status.failures,
+                    feed_status.breaker,
+                );
+            } else if was_open {
+                log::info!("rss fetch: '{}' recovered, breaker closed", feed.url);
+            }
+            if started.elapsed() > Duration::from_secs(interval) {
+                log::warn!(
+                    "rss fetch: '{}' took {}s, overrunning its {interval}s interval",
+                    feed.url,
+                    started.elapsed().as_secs()
+                );
+            }
+        }
+        _ => {
+            log::error!("rss fetch: could not obtain StatusDb lock")
+        }
+    }
+}
+
+/// Makes what is in the store now the photos which are served.
+pub async fn publish(index: &SharedIndex, db: &FeedDb) {
+    let index = index.clone();
+    if let Err(err) = blocking(db, move |db| index.publish(db.as_ref())).await {
+        log::error!("rss fetch: could not publish the photo index: {err}")
+    }
+}
+
+fn is_due(feed: &Feed, status: &StatusDb, at: u64) -> bool {
+    match status.lock() {
+        Ok(hash) => hash
+            .get(&feed.url)
+            .is_none_or(|feed_status| feed_status.next_fetch <= at),
+        Err(_) => false,
+    }
+}
+
+/// Drops the photos of every feed no longer in the list, marking its status as evicted
+/// (or clearing the status of a feed listed again), and returns the urls evicted just now.
+fn reconcile(active: &[Feed], db: &FeedDb, status: &StatusDb) -> Vec<String> {
+    let listed: HashSet<&str> = active.iter().map(|feed| feed.url.as_str()).collect();
+    let mut evicted = BTreeMap::<String, usize>::new();
+    match db.feeds() {
+        Ok(urls) => {
+            for url in urls
+                .into_iter()
+                .filter(|url| !listed.contains(url.as_str()))
+            {
+                match db.remove_feed(&url) {
+                    Ok(photos) => {
+                        evicted.insert(url, photos);
+                    }
+                    Err(err) => log::error!("rss fetch: could not evict '{url}': {err}"),
+                }
+            }
+        }
+        Err(err) => log::error!("rss fetch: could not list the stored feeds: {err}"),
+    }
+    if let Ok(mut hash) = status.lock() {
+        let at = now();
+        for (url, feed_status) in hash.iter_mut() {
+            match (listed.contains(url.as_str()), feed_status.evicted) {
+                // back in the list, so start over as if it were new
+                (true, Some(_)) => *feed_status = FeedStatus::default(),
+                (false, None) => {
+                    feed_status.evicted = Some(at);
+                    evicted.entry(url.to_string()).or_default();
+                }
+                // a fetch which was in flight at eviction time has put its photos back
+                (false, Some(_)) => {}
+                (true, None) => {}
+            }
+        }
+    }
+    for (url, photos) in &evicted {
+        log::info!("rss fetch: evicted '{url}' ({photos} photos), which is no longer listed");
+    }
+    evicted.into_keys().collect()
+}
+
+/// `reconcile`, off the async workers, publishing what is left if any feed was evicted.
+async fn evict(active: &[Feed], db: &FeedDb, index: &SharedIndex, status: &StatusDb) {
+    let (active, status) = (active.to_vec(), status.clone());
+    match blocking(db, move |db| Ok(reconcile(&active, db, &status))).await {
+        Ok(evicted) if !evicted.is_empty() => publish(index, db).await,
+        Ok(_) => {}
+        Err(err) => log::error!("rss fetch: could not evict the feeds no longer listed: {err}"),
+    }
+}
+
+fn reschedule(old: &[Feed], new: &[Feed], status: &StatusDb, config: &LoaderConfig) {
+    // a new interval in the list applies from the last fetch, rather than the next one
+    let Ok(mut hash) = status.lock() else {
+        return;
+    };
+    for feed in new {
+        if old
+            .iter()
+            .any(|before| before.url == feed.url && before.interval != feed.interval)
+            && let Some(feed_status) = hash.get_mut(&feed.url)
+            && feed_status.failures == 0
+        {
+            let interval = config.interval_for(feed, &feed_status.report);
+            feed_status.interval = interval;
+            feed_status.next_fetch = feed_status.last_fetched + interval;
+        }
+    }
+}
+
+/// Downloads and hashes the new images of each feed sent down `feeds` (no more than
+/// `concurrency` images at a time across feeds), then serves its photos again, with near
+/// duplicates merged. This runs apart from the fetches, so downloading images never holds
+/// up a slot for fetching feeds, and through a client without the cache, so images (only
+/// ever downloaded once) never push feeds out of it.
+async fn hash_images(
+    mut feeds: mpsc::UnboundedReceiver<String>,
+    client: HttpClient,
+    db: FeedDb,
+    index: SharedIndex,
+    concurrency: usize,
+) {
+    let Some(hashes) = index.image_hashes().cloned() else {
+        return;
+    };
+    let slots = Arc::new(Semaphore::new(concurrency.max(1)));
+    let mut hashing = JoinSet::new();
+    while let Some(url) = feeds.recv().await {
+        let (hashes, client, db, index, slots) = (
+            hashes.clone(),
+            client.clone(),
+            db.clone(),
+            index.clone(),
+            slots.clone(),
+        );
+        hashing.spawn(async move {
+            let feed = url.clone();
+            let Ok(photos) = blocking(&db, move |db| db.feed_photos(&feed)).await else {
+                return;
+            };
+            let hashed = hashes.hash_images(&client, &photos, &slots).await;
+            if hashed > 0 {
+                log::debug!("image hash: hashed {hashed} new images of '{url}'");
+                publish(&index, &db).await;
+            }
         });
+        while hashing.try_join_next().is_some() {}
+    }
+}
+
+type HostSlots = HashMap<String, Arc<Semaphore>>;
+
+/// Forgets the hosts no feed in the list is on any more, unless a fetch from one is still
+/// under way (a host listed again meanwhile must not get a second set of slots).
+fn prune_hosts(hosts: &mut HostSlots, active: &[Feed]) {
+    let listed: HashSet<String> = active.iter().map(|feed| host(&feed.url)).collect();
+    hosts.retain(|name, slots| listed.contains(name) || Arc::strong_count(slots) > 1);
+}
 
-        interval.tick().await;
+pub async fn background(
+    mut feeds: watch::Receiver<Vec<Feed>>,
+    db: FeedDb,
+    index: SharedIndex,
+    status: StatusDb,
+    client: HttpClient,
+    config: LoaderConfig,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    // load the FeedDb in the background, checking regularly for feeds which are due;
+    // feeds never fetched before (including any added to the list since) are due right away,
+    // so there is something to serve
+    let mut ticker = time::interval(Duration::from_secs(config.min_interval.clamp(1, 60)));
+    // if the loop falls behind, wait for the next tick instead of bursting to catch up
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+    let in_flight = InFlight::default();
+    let slots = Arc::new(Semaphore::new(config.concurrency.max(1)));
+    let mut hosts = HostSlots::new();
+    let mut active = feeds.borrow_and_update().clone();
+    let mut reloading = true;
+    let mut fetches = JoinSet::new();
+    let (to_hash, feeds_to_hash) = mpsc::unbounded_channel();
+    let hashing = tokio::spawn(hash_images(
+        feeds_to_hash,
+        client.without_cache(),
+        db.clone(),
+        index.clone(),
+        config.hash_concurrency,
+    ));
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            // a dropped sender means nobody is left to say otherwise, so stop all the same
+            _ = shutdown.wait_for(|stopping| *stopping) => break,
+            changed = feeds.changed(), if reloading => {
+                if changed.is_err() {
+                    // the list can no longer change, so keep fetching the last one
+                    reloading = false;
+                    continue;
+                }
+                let list = feeds.borrow_and_update().clone();
+                reschedule(&active, &list, &status, &config);
+                active = list;
+                prune_hosts(&mut hosts, &active);
+            }
+        }
+
+        // drops the feeds no longer listed, and catches photos put back by fetches which
+        // were still in flight at the last reload
+        evict(&active, &db, &index, &status).await;
+
+        let at = now();
+        for feed in active.iter().filter(|feed| is_due(feed, &status, at)) {
+            // only one fetch of any feed is ever in flight
+            let Some(guard) = FetchGuard::claim(&feed.url, &in_flight) else {
+                continue;
+            };
+            let host_slots = hosts
+                .entry(host(&feed.url))
+                .or_insert_with(|| Arc::new(Semaphore::new(config.per_host.max(1))))
+                .clone();
+            let slots = slots.clone();
+            let feed = feed.clone();
+            let db = db.clone();
+            let index = index.clone();
+            let status = status.clone();
+            let config = config.clone();
+            let client = client.clone();
+            let to_hash = to_hash.clone();
+            fetches.spawn(async move {
+                let _guard = guard;
+                // wait on the host first, so a busy host does not tie up a slot others could use
+                let Ok(_host) = host_slots.acquire_owned().await else {
+                    return;
+                };
+                let Ok(_slot) = slots.acquire_owned().await else {
+                    return;
+                };
+                fetch_feed(&client, &feed, &db, &index, &status, &config, &to_hash).await;
+            });
+        }
+
+        while fetches.try_join_next().is_some() {}
+    }
+
+    // fetches still waiting for a slot never start, and those under way get a while to finish
+    slots.close();
+    for host_slots in hosts.values() {
+        host_slots.close();
     }
+    let running = fetches.len();
+    if running > 0 {
+        log::info!("rss fetch: stopping, waiting for {running} fetches in flight");
+    }
+    let deadline = Duration::from_secs(config.shutdown_timeout);
+    if time::timeout(deadline, async {
+        while fetches.join_next().await.is_some() {}
+    })
+    .await
+    .is_err()
+    {
+        log::warn!(
+            "rss fetch: abandoning {} fetches still in flight after {}s",
+            fetches.len(),
+            config.shutdown_timeout
+        );
+        fetches.shutdown().await;
+    }
+    // images still being downloaded are not worth waiting for: the photos are stored, and
+    // any image left unhashed is hashed after the next fetch of its feed
+    hashing.abort();
+    log::info!("rss fetch: stopped");
 }
+
+#[path = "loader_test.rs"]
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,722 @@
+use super::*;
+use crate::cache::CacheConfig;
+use crate::http::HttpConfig;
+use crate::parser::RefreshHint;
+use crate::store::MemoryStore;
+use crate::test_server::{reply, response, serve, serve_nothing};
+
+#[test]
+fn feed_intervals_prefer_the_feed_list_then_the_channel() {
+    let config = LoaderConfig::default();
+    let hourly = ExtractionReport {
+        refresh: RefreshHint {
+            update_period: Some(3600 * 6),
+            ..RefreshHint::default()
+        },
+        ..ExtractionReport::default()
+    };
+
+    // nothing said anywhere: the global interval
+    let feed = Feed::new("https://aeon.co/feed.rss");
+    assert_eq!(
+        config.interval_for(&feed, &ExtractionReport::default()),
+        3600
+    );
+
+    // the channel's own sy:updatePeriod
+    assert_eq!(config.interval_for(&feed, &hourly), 3600 * 6);
+
+    // an explicit setting in the feed list wins over the channel
+    let feed = Feed {
+        interval: Some(900),
+        ..Feed::new("https://rss.nytimes.com/services/xml/rss/nyt/HomePage.xml")
+    };
+    assert_eq!(config.interval_for(&feed, &hourly), 900);
+}
+
+#[test]
+fn feed_intervals_stay_within_bounds() {
+    let config = LoaderConfig {
+        min_interval: 600,
+        max_interval: 7200,
+        ..LoaderConfig::default()
+    };
+
+    let eager = Feed {
+        interval: Some(60),
+        ..Feed::new("https://www.bbc.co.uk/news/rss.xml")
+    };
+    assert_eq!(
+        config.interval_for(&eager, &ExtractionReport::default()),
+        600
+    );
+
+    let lazy = ExtractionReport {
+        refresh: RefreshHint {
+            ttl: Some(86400 * 7),
+            ..RefreshHint::default()
+        },
+        ..ExtractionReport::default()
+    };
+    let feed = Feed::new("https://aeon.co/feed.rss");
+    assert_eq!(config.interval_for(&feed, &lazy), 7200);
+
+    // however large a ttl the channel gives
+    let huge = ExtractionReport {
+        refresh: RefreshHint {
+            ttl: Some(u64::MAX),
+            ..RefreshHint::default()
+        },
+        ..ExtractionReport::default()
+    };
+    assert_eq!(config.interval_for(&feed, &huge), 7200);
+}
+
+#[test]
+fn failing_feeds_back_off_and_then_open_their_breaker() {
+    let config = LoaderConfig {
+        failure_threshold: 3,
+        max_backoff: 4 * 3600,
+        probe_interval: 86400,
+        ..LoaderConfig::default()
+    };
+    let unavailable = FeedError::Status {
+        code: 503,
+        retry_after: None,
+    };
+
+    let mut feed_status = FeedStatus::default();
+    feed_status.record(Ok(ExtractionReport::default()), &config);
+    assert_eq!(feed_status.delay(3600, &config), 3600);
+
+    feed_status.record(Err(unavailable.clone()), &config);
+    assert_eq!(feed_status.breaker, Breaker::Closed);
+    assert_eq!(feed_status.delay(3600, &config), 2 * 3600);
+
+    // capped at the maximum backoff
+    feed_status.record(Err(unavailable.clone()), &config);
+    assert_eq!(feed_status.delay(3600, &config), 4 * 3600);
+
+    // after enough failures in a row, only probe occasionally
+    feed_status.record(Err(unavailable.clone()), &config);
+    assert_eq!(feed_status.failures, 3);
+    assert_eq!(feed_status.breaker, Breaker::Open);
+    assert_eq!(feed_status.delay(3600, &config), 86400);
+
+    // one success closes the breaker again
+    feed_status.breaker = Breaker::HalfOpen;
+    feed_status.record(Ok(ExtractionReport::default()), &config);
+    assert_eq!(feed_status.failures, 0);
+    assert_eq!(feed_status.breaker, Breaker::Closed);
+    assert_eq!(feed_status.errors.get("status"), Some(&3));
+}
+
+#[test]
+fn permanent_errors_are_only_probed() {
+    let config = LoaderConfig {
+        failure_threshold: 3,
+        probe_interval: 86400,
+        ..LoaderConfig::default()
+    };
+    for err in [
+        FeedError::Status {
+            code: 404,
+            retry_after: None,
+        },
+        FeedError::Status {
+            code: 410,
+            retry_after: None,
+        },
+        FeedError::Parse {
+            message: "unexpected end of file".to_string(),
+        },
+    ] {
+        let mut feed_status = FeedStatus::default();
+        feed_status.record(Err(err.clone()), &config);
+        // no point retrying in an hour, so the breaker opens right away
+        assert_eq!(feed_status.breaker, Breaker::Open, "{err}");
+        assert_eq!(feed_status.delay(3600, &config), 86400, "{err}");
+    }
+}
+
+#[test]
+fn unchanged_feeds_count_as_hits() {
+    let config = LoaderConfig::default();
+    let report = ExtractionReport {
+        items: 2,
+        accepted: 1,
+        ..ExtractionReport::default()
+    };
+    let mut feed_status = FeedStatus::default();
+    feed_status.record(Ok(report.clone()), &config);
+    feed_status.record(
+        Err(FeedError::Status {
+            code: 500,
+            retry_after: None,
+        }),
+        &config,
+    );
+    feed_status.record_not_modified();
+    feed_status.record_not_modified();
+
+    assert_eq!((feed_status.hits, feed_status.misses), (2, 1));
+    assert_eq!(feed_status.failures, 0);
+    assert_eq!(feed_status.last_error, None);
+    // what the last full fetch found still holds
+    assert_eq!(feed_status.report.accepted, report.accepted);
+}
+
+#[test]
+fn retry_after_is_honored() {
+    let config = LoaderConfig::default();
+    let mut feed_status = FeedStatus::default();
+    feed_status.record(
+        Err(FeedError::Status {
+            code: 503,
+            retry_after: Some(4 * 3600),
+        }),
+        &config,
+    );
+    // longer than the backoff would have been
+    assert_eq!(feed_status.delay(900, &config), 4 * 3600);
+
+    feed_status.record(
+        Err(FeedError::Status {
+            code: 429,
+            retry_after: Some(60),
+        }),
+        &config,
+    );
+    // shorter than the backoff, which still applies
+    assert_eq!(feed_status.delay(900, &config), 4 * 900);
+
+    // and no longer than the probe interval, however long the server asks for
+    feed_status.record(
+        Err(FeedError::Status {
+            code: 503,
+            retry_after: Some(u64::MAX),
+        }),
+        &config,
+    );
+    assert_eq!(feed_status.delay(900, &config), config.probe_interval);
+    let before = now();
+    feed_status.schedule(900, &config);
+    assert!(feed_status.next_fetch >= before + config.probe_interval);
+    assert!(feed_status.next_fetch <= now() + config.probe_interval + config.jitter);
+}
+
+#[test]
+fn changed_intervals_apply_from_the_last_fetch() {
+    let config = LoaderConfig::default();
+    let old = vec![Feed::new("https://aeon.co/feed.rss")];
+    let new = vec![Feed {
+        interval: Some(900),
+        ..Feed::new("https://aeon.co/feed.rss")
+    }];
+    let status: StatusDb = Arc::new(Mutex::new(HashMap::from([(
+        old[0].url.clone(),
+        FeedStatus {
+            last_fetched: 1700841600,
+            interval: 3600,
+            next_fetch: 1700845200,
+            ..FeedStatus::default()
+        },
+    )])));
+
+    reschedule(&old, &new, &status, &config);
+    let status = status.lock().unwrap();
+    let aeon = status.get("https://aeon.co/feed.rss").unwrap();
+    assert_eq!(aeon.interval, 900);
+    assert_eq!(aeon.next_fetch, 1700841600 + 900);
+}
+
+#[test]
+fn feeds_no_longer_listed_are_evicted() {
+    let france24 = Feed::new("https://www.france24.com/en/rss");
+    let aeon = Feed::new("https://aeon.co/feed.rss");
+    let db: FeedDb = Arc::new(MemoryStore::default());
+    let status: StatusDb = Arc::new(Mutex::new(HashMap::new()));
+    for feed in [&france24, &aeon] {
+        db.upsert_feed(&feed.url, vec![NewsPhoto::new()]).unwrap();
+        status
+            .lock()
+            .unwrap()
+            .insert(feed.url.clone(), FeedStatus::default());
+    }
+
+    let active = vec![aeon.clone()];
+    assert_eq!(reconcile(&active, &db, &status), vec![france24.url.clone()]);
+    assert_eq!(db.feeds().unwrap(), vec![aeon.url.clone()]);
+    assert!(status.lock().unwrap()[&france24.url].evicted.is_some());
+
+    // photos put back by a fetch still in flight are dropped again
+    db.upsert_feed(&france24.url, vec![NewsPhoto::new()])
+        .unwrap();
+    assert_eq!(reconcile(&active, &db, &status), vec![france24.url.clone()]);
+    assert!(reconcile(&active, &db, &status).is_empty());
+
+    // listed again, the feed starts over
+    let active = vec![aeon, france24.clone()];
+    assert!(reconcile(&active, &db, &status).is_empty());
+    assert_eq!(status.lock().unwrap()[&france24.url].evicted, None);
+}
+
+fn photo(story: &str) -> NewsPhoto {
+    NewsPhoto {
+        image_url: format!("{story}.jpg"),
+        story_url: story.to_string(),
+        ..NewsPhoto::new()
+    }
+}
+
+#[test]
+fn photos_are_kept_for_a_while_after_leaving_their_feed() {
+    let hour = 3600;
+    let start = 1700841600;
+
+    let known = merge(
+        Vec::new(),
+        vec![photo("a"), photo("b"), photo("a")],
+        start,
+        24 * hour,
+    );
+    assert_eq!(known.len(), 2);
+
+    // "a" dropped out of the feed, "c" is new
+    let known = merge(known, vec![photo("b"), photo("c")], start + hour, 24 * hour);
+    let seen: Vec<(&str, u64, u64)> = known
+        .iter()
+        .map(|photo| (photo.identity(), photo.first_seen, photo.last_seen))
+        .collect();
+    assert_eq!(
+        seen,
+        vec![
+            ("b", start, start + hour),
+            ("c", start + hour, start + hour),
+            ("a", start, start),
+        ]
+    );
+
+    // an unchanged feed still holds "b" and "c", but "a" has now been gone too long
+    let known = still_seen(known, start + 25 * hour, 24 * hour);
+    let seen: Vec<(&str, u64, u64)> = known
+        .iter()
+        .map(|photo| (photo.identity(), photo.first_seen, photo.last_seen))
+        .collect();
+    assert_eq!(
+        seen,
+        vec![
+            ("b", start, start + 25 * hour),
+            ("c", start + hour, start + 25 * hour),
+        ]
+    );
+}
+
+#[test]
+fn old_photos_expire_by_publication_or_first_sight() {
+    let config = LoaderConfig {
+        max_age: 7 * 86400,
+        ..LoaderConfig::default()
+    };
+    let unlimited = Feed {
+        max_age: Some(0),
+        ..Feed::new("https://aeon.co/feed.rss")
+    };
+    let daily = Feed {
+        max_age: Some(86400),
+        ..Feed::new("https://www.quantamagazine.org/feed/")
+    };
+    assert_eq!(
+        config.max_age_for(&Feed::new("https://www.france24.com/en/rss")),
+        Some(7 * 86400)
+    );
+    assert_eq!(config.max_age_for(&unlimited), None);
+    assert_eq!(config.max_age_for(&daily), Some(86400));
+
+    let at = 1700841600;
+    let fresh = NewsPhoto {
+        published: Some(at - 3600),
+        ..photo("fresh")
+    };
+    let stale = NewsPhoto {
+        published: Some(at - 2 * 86400),
+        ..photo("stale")
+    };
+    // without a publication date, the first sighting counts
+    let undated = NewsPhoto {
+        first_seen: at - 3 * 86400,
+        ..photo("undated")
+    };
+    let mut photos = merge(
+        vec![undated.clone()],
+        vec![fresh, stale, undated],
+        at,
+        86400,
+    );
+    assert_eq!(expire(&mut photos, Some(86400), at), 2);
+    let expired: Vec<(&str, bool)> = photos
+        .iter()
+        .map(|photo| (photo.identity(), photo.expired(at)))
+        .collect();
+    assert_eq!(
+        expired,
+        vec![("fresh", false), ("stale", true), ("undated", true)]
+    );
+    assert_eq!(photos[0].expires_at, Some(at - 3600 + 86400));
+    assert!(photos[0].expired(at + 86400));
+}
+
+#[test]
+fn expired_photos_stay_expired_until_they_leave_their_feed() {
+    let at = 1700841600;
+    let max_age = Some(86400);
+    let undated = photo("undated");
+    let mut photos = merge(Vec::new(), vec![undated.clone()], at, 86400);
+    assert_eq!(expire(&mut photos, max_age, at), 0);
+
+    // still in the feed a day later, when it expires, and again after that
+    for later in [at + 86400, at + 86400 + 3600] {
+        photos = merge(photos, vec![undated.clone()], later, 86400);
+        assert_eq!(expire(&mut photos, max_age, later), 1);
+        assert_eq!(photos.len(), 1);
+        assert_eq!(photos[0].first_seen, at);
+        assert!(photos[0].expired(later));
+    }
+
+    // and once the feed drops it, so does the store
+    let gone = at + 2 * 86400;
+    photos = merge(photos, Vec::new(), gone, 86400);
+    assert_eq!(expire(&mut photos, max_age, gone), 0);
+    assert!(photos.is_empty());
+}
+
+#[tokio::test]
+async fn shutdown_waits_for_fetches_in_flight_up_to_the_deadline() {
+    // a host which tells the test of each fetch, but never answers it
+    let (fetching, mut fetches) = tokio::sync::mpsc::unbounded_channel();
+    let address = serve(move |_, socket| {
+        let _ = fetching.send(());
+        async move {
+            let _socket = socket;
+            std::future::pending::<()>().await;
+        }
+    })
+    .await;
+    let feed = Feed::new(&format!("http://{address}/rss"));
+    let client = uncached_client();
+    let config = LoaderConfig {
+        shutdown_timeout: 1,
+        ..LoaderConfig::default()
+    };
+    let db: FeedDb = Arc::new(MemoryStore::default());
+    let status: StatusDb = Arc::new(Mutex::new(HashMap::new()));
+    let (_list, feeds) = watch::channel(vec![feed]);
+    let (stop, shutdown) = watch::channel(false);
+    let loading = tokio::spawn(background(
+        feeds,
+        db,
+        SharedIndex::default(),
+        status,
+        client,
+        config,
+        shutdown,
+    ));
+
+    // once the fetch is under way, it holds the loader up until the deadline, and no longer
+    fetches.recv().await.unwrap();
+    let started = Instant::now();
+    stop.send(true).unwrap();
+    time::timeout(Duration::from_secs(5), loading)
+        .await
+        .expect("the loader did not stop")
+        .unwrap();
+    assert!(started.elapsed() >= Duration::from_secs(1));
+}
+
+/// The fetches a test feed server is answering right now, and the most it ever answered at once.
+#[derive(Debug, Default)]
+struct Connections {
+    open: HashMap<String, usize>,
+    most: HashMap<String, usize>,
+    most_overall: usize,
+}
+
+// serves the aeon fixture at any path, taking `slow` to answer those starting with /slow,
+// and counting the requests being answered by the host they were sent to
+async fn serve_feeds(slow: Duration) -> (u16, Arc<Mutex<Connections>>) {
+    let connections = Arc::new(Mutex::new(Connections::default()));
+    let counted = connections.clone();
+    let body = std::fs::read_to_string("tests/fixtures/aeon.xml").unwrap();
+    let address = serve(move |request, socket| {
+        let connections = counted.clone();
+        let body = body.clone();
+        async move {
+            let host = request
+                .header("host")
+                .and_then(|host| host.split(':').next())
+                .unwrap_or_default()
+                .to_string();
+            {
+                let mut connections = connections.lock().unwrap();
+                let open = connections.open.entry(host.clone()).or_default();
+                *open += 1;
+                let open = *open;
+                let most = connections.most.entry(host.clone()).or_default();
+                *most = open.max(*most);
+                let overall = connections.open.values().sum::<usize>();
+                connections.most_overall = overall.max(connections.most_overall);
+            }
+            time::sleep(match request.path.starts_with("/slow") {
+                true => slow,
+                false => Duration::from_millis(200),
+            })
+            .await;
+            // counted as closed before answering, so the next fetch cannot overlap this one
+            *connections.lock().unwrap().open.entry(host).or_default() -= 1;
+            reply(socket, response("200 OK", body)).await;
+        }
+    })
+    .await;
+    (address.port(), connections)
+}
+
+fn uncached_client() -> HttpClient {
+    HttpClient::new(&HttpConfig {
+        cache: CacheConfig {
+            enabled: false,
+            ..CacheConfig::default()
+        },
+        ..HttpConfig::default()
+    })
+    .unwrap()
+}
+
+#[tokio::test]
+async fn fetches_stay_within_the_global_and_per_host_limits() {
+    let (port, connections) = serve_feeds(Duration::ZERO).await;
+    // two hosts, as far as the loader can tell, with four feeds each
+    let list: Vec<Feed> = ["127.0.0.1", "localhost"]
+        .iter()
+        .flat_map(|host| (0..4).map(move |n| Feed::new(&format!("http://{host}:{port}/{n}"))))
+        .collect();
+    let config = LoaderConfig {
+        concurrency: 3,
+        per_host: 2,
+        ..LoaderConfig::default()
+    };
+    let db: FeedDb = Arc::new(MemoryStore::default());
+    let (_list, feeds) = watch::channel(list);
+    let (stop, shutdown) = watch::channel(false);
+    let loading = tokio::spawn(background(
+        feeds,
+        db.clone(),
+        SharedIndex::default(),
+        Arc::new(Mutex::new(HashMap::new())),
+        uncached_client(),
+        config,
+        shutdown,
+    ));
+
+    time::timeout(Duration::from_secs(10), async {
+        while db.feeds().unwrap().len() < 8 {
+            time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("not every feed was fetched");
+    stop.send(true).unwrap();
+    loading.await.unwrap();
+
+    let connections = connections.lock().unwrap();
+    assert_eq!(connections.most.get("127.0.0.1"), Some(&2));
+    assert_eq!(connections.most.get("localhost"), Some(&2));
+    assert_eq!(connections.most_overall, 3);
+}
+
+#[tokio::test]
+async fn each_feed_is_stored_as_soon_as_it_is_fetched() {
+    let (port, connections) = serve_feeds(Duration::from_secs(30)).await;
+    let quick = format!("http://127.0.0.1:{port}/rss");
+    let list = vec![
+        Feed::new(&format!("http://127.0.0.1:{port}/slow")),
+        Feed::new(&quick),
+    ];
+    let config = LoaderConfig {
+        shutdown_timeout: 0,
+        ..LoaderConfig::default()
+    };
+    let db: FeedDb = Arc::new(MemoryStore::default());
+    let index = SharedIndex::default();
+    let (_list, feeds) = watch::channel(list);
+    let (stop, shutdown) = watch::channel(false);
+    let loading = tokio::spawn(background(
+        feeds,
+        db.clone(),
+        index.clone(),
+        Arc::new(Mutex::new(HashMap::new())),
+        uncached_client(),
+        config,
+        shutdown,
+    ));
+
+    // the quick feed is stored and served while the slow one is still being answered
+    time::timeout(Duration::from_secs(10), async {
+        while db.feeds().unwrap().is_empty() {
+            time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("the quick feed was not stored");
+    assert_eq!(db.feeds().unwrap(), vec![quick]);
+    assert_eq!(index.load().counts(), (1, 20));
+    assert_eq!(connections.lock().unwrap().open.get("127.0.0.1"), Some(&1));
+
+    stop.send(true).unwrap();
+    loading.await.unwrap();
+}
+
+#[test]
+fn jitter_stays_within_its_bounds() {
+    let config = LoaderConfig {
+        jitter: 30,
+        ..LoaderConfig::default()
+    };
+    for _ in 0..1000 {
+        assert!(config.jitter_for(3600) <= 30);
+        // never more than half the interval, so fetches cannot drift into each other
+        assert!(config.jitter_for(10) <= 5);
+        assert_eq!(config.jitter_for(1), 0);
+    }
+    let never = LoaderConfig {
+        jitter: 0,
+        ..LoaderConfig::default()
+    };
+    assert_eq!(never.jitter_for(3600), 0);
+
+    // a feed never fetched before is due right away, and only its next fetch is jittered
+    let feed = Feed::new("https://example.com/rss");
+    let status: StatusDb = Arc::new(Mutex::new(HashMap::new()));
+    assert!(is_due(&feed, &status, now()));
+    let mut feed_status = FeedStatus::default();
+    let before = now();
+    feed_status.schedule(3600, &config);
+    assert!(feed_status.next_fetch >= before + 3600);
+    assert!(feed_status.next_fetch <= now() + 3600 + 30);
+}
+
+#[test]
+fn feeds_in_flight_cannot_be_claimed_again() {
+    let in_flight = InFlight::default();
+    let guard = FetchGuard::claim("https://example.com/rss", &in_flight);
+    assert!(guard.is_some());
+    assert!(FetchGuard::claim("https://example.com/rss", &in_flight).is_none());
+    assert!(FetchGuard::claim("https://example.com/other", &in_flight).is_some());
+    drop(guard);
+    assert!(FetchGuard::claim("https://example.com/rss", &in_flight).is_some());
+}
+
+#[tokio::test]
+async fn a_feed_still_in_flight_is_not_fetched_again() {
+    let (port, connections) = serve_feeds(Duration::from_secs(30)).await;
+    // the loader checks every second, and the feed stays due until its fetch is done
+    let config = LoaderConfig {
+        min_interval: 1,
+        shutdown_timeout: 0,
+        ..LoaderConfig::default()
+    };
+    let (_list, feeds) = watch::channel(vec![Feed::new(&format!("http://127.0.0.1:{port}/slow"))]);
+    let (stop, shutdown) = watch::channel(false);
+    let loading = tokio::spawn(background(
+        feeds,
+        Arc::new(MemoryStore::default()),
+        SharedIndex::default(),
+        Arc::new(Mutex::new(HashMap::new())),
+        uncached_client(),
+        config,
+        shutdown,
+    ));
+
+    time::sleep(Duration::from_millis(3500)).await;
+    assert_eq!(connections.lock().unwrap().most.get("127.0.0.1"), Some(&1));
+
+    stop.send(true).unwrap();
+    loading.await.unwrap();
+}
+
+#[test]
+fn hosts_no_longer_listed_are_forgotten() {
+    let mut hosts = HostSlots::new();
+    for name in ["example.com", "example.org", "example.net"] {
+        hosts.insert(name.to_string(), Arc::new(Semaphore::new(2)));
+    }
+    // a fetch from example.net is still under way
+    let _fetching = hosts["example.net"].clone();
+    prune_hosts(&mut hosts, &[Feed::new("https://example.com/rss")]);
+    let mut left: Vec<&str> = hosts.keys().map(String::as_str).collect();
+    left.sort();
+    assert_eq!(left, vec!["example.com", "example.net"]);
+}
+
+#[tokio::test]
+async fn image_downloads_hold_up_neither_fetches_nor_shutdown() {
+    let images_at = serve_nothing().await;
+    // a host for the feeds, each with a photo on one which never answers
+    let feeds_at = serve(move |request, socket| async move {
+        let (path, host) = (&request.path, request.header("host").unwrap_or_default());
+        let body = format!(
+            r#"<?xml version="1.0"?>
+<rss version="2.0" xmlns:media="http://search.yahoo.com/mrss/"><channel>
+<title>Test</title><link>http://{host}/</link><description>Test</description>
+<item><title>Story</title><link>http://{host}{path}/story</link>
+<media:content url="http://{images_at}{path}.jpg" medium="image" /></item>
+</channel></rss>"#
+        );
+        reply(socket, response("200 OK", body)).await;
+    })
+    .await;
+
+    // one fetch at a time, so a fetch waiting on its images would hold up the other feed
+    let config = LoaderConfig {
+        concurrency: 1,
+        ..LoaderConfig::default()
+    };
+    let db: FeedDb = Arc::new(MemoryStore::default());
+    let index = SharedIndex::with_image_hashes(crate::phash::ImageHashes::new(6));
+    let (_list, feeds) = watch::channel(vec![
+        Feed::new(&format!("http://{feeds_at}/a")),
+        Feed::new(&format!("http://{feeds_at}/b")),
+    ]);
+    let (stop, shutdown) = watch::channel(false);
+    let loading = tokio::spawn(background(
+        feeds,
+        db.clone(),
+        index.clone(),
+        Arc::new(Mutex::new(HashMap::new())),
+        uncached_client(),
+        config,
+        shutdown,
+    ));
+
+    time::timeout(Duration::from_secs(10), async {
+        while db.feeds().unwrap().len() < 2 {
+            time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("a fetch was held up by images being downloaded");
+    // nor are their photos held back until then
+    time::timeout(Duration::from_secs(5), async {
+        while index.load().counts() != (2, 2) {
+            time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("the photos were held back by images being downloaded");
+
+    // and the images still being downloaded do not hold up shutdown either
+    stop.send(true).unwrap();
+    time::timeout(Duration::from_secs(5), loading)
+        .await
+        .expect("the loader waited on images being downloaded")
+        .unwrap();
+}
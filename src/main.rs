@@ -1,51 +1,207 @@
 use env_logger::Env;
-use log::info;
+use log::{info, warn};
+use photojournalism::cache;
+use photojournalism::cli;
+use photojournalism::feeds;
+use photojournalism::http;
+use photojournalism::index;
 use photojournalism::loader;
-use photojournalism::parser;
+use photojournalism::phash;
 use photojournalism::server;
+use photojournalism::snapshot;
+use photojournalism::sqlite::SqliteStore;
+use photojournalism::store::MemoryStore;
 use std::collections::HashMap;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
 use std::net::TcpListener;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::sync::{Arc, Mutex};
+use tokio::signal::unix::{SignalKind, signal};
+use tokio::sync::watch;
+
+fn env_or<T: FromStr>(name: &str, default: T) -> T {
+    // optional settings fall back to their default when missing or invalid
+    match std::env::var(name) {
+        Ok(value) => value.parse().unwrap_or(default),
+        Err(_) => default,
+    }
+}
 
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
     env_logger::init_from_env(Env::default().default_filter_or("info"));
 
+    let args: Vec<String> = std::env::args().collect();
+    if cli::is_command(&args) {
+        return cli::run(&args).await;
+    }
+
     let static_folder = std::env::var("PHOTOJOURNALISM_STATIC_PATH")
         .expect("env var 'PHOTOJOURNALISM_STATIC_PATH' not defined");
 
     let feed_list = std::env::var("PHOTOJOURNALISM_FEED_LIST")
         .expect("env var 'PHOTOJOURNALISM_FEED_LIST' not defined");
-    let feed_path = Path::new(&feed_list);
-
-    let feeds = match File::open(&feed_path) {
-        Ok(file) => {
-            let file = BufReader::new(file);
-            file.lines()
-                .filter(|line| line.is_ok())
-                .map(|line| line.unwrap())
-                .filter(|text| !text.starts_with("#"))
-                .collect::<Vec<_>>()
-        }
-        Err(_) => panic!("cannot find list of RSS feeds at '{feed_list}'"),
+    let feed_path = PathBuf::from(&feed_list);
+
+    let feeds = match feeds::read_feed_list(&feed_path) {
+        Ok(feeds) => feeds,
+        Err(err) => panic!("cannot read list of RSS feeds at '{feed_list}': {err}"),
     };
 
     let interval = std::env::var("PHOTOJOURNALISM_FETCH_INTERVAL")
         .expect("env var 'PHOTOJOURNALISM_FETCH_INTERVAL' not defined");
+    #[allow(clippy::manual_unwrap_or)]
     let fetch_interval: u64 = match interval.parse() {
         Ok(i) => i,
         Err(_) => 3600, // default to one hour
     };
 
-    let feed_db = Arc::new(Mutex::new(HashMap::<String, Vec<parser::NewsPhoto>>::new()));
+    let defaults = loader::LoaderConfig::default();
+    let loader_config = loader::LoaderConfig {
+        interval: fetch_interval,
+        min_interval: env_or("PHOTOJOURNALISM_MIN_FETCH_INTERVAL", defaults.min_interval),
+        max_interval: env_or("PHOTOJOURNALISM_MAX_FETCH_INTERVAL", defaults.max_interval),
+        concurrency: env_or("PHOTOJOURNALISM_FETCH_CONCURRENCY", defaults.concurrency),
+        per_host: env_or("PHOTOJOURNALISM_FETCH_PER_HOST", defaults.per_host),
+        jitter: env_or("PHOTOJOURNALISM_FETCH_JITTER", defaults.jitter),
+        failure_threshold: env_or(
+            "PHOTOJOURNALISM_FAILURE_THRESHOLD",
+            defaults.failure_threshold,
+        ),
+        max_backoff: env_or("PHOTOJOURNALISM_MAX_BACKOFF", defaults.max_backoff),
+        probe_interval: env_or("PHOTOJOURNALISM_PROBE_INTERVAL", defaults.probe_interval),
+        retention: env_or("PHOTOJOURNALISM_RETENTION", defaults.retention),
+        max_age: env_or("PHOTOJOURNALISM_MAX_AGE", defaults.max_age),
+        shutdown_timeout: env_or(
+            "PHOTOJOURNALISM_SHUTDOWN_TIMEOUT",
+            defaults.shutdown_timeout,
+        ),
+        hash_concurrency: env_or(
+            "PHOTOJOURNALISM_HASH_CONCURRENCY",
+            defaults.hash_concurrency,
+        ),
+    };
+
+    let http_defaults = http::HttpConfig::default();
+    let cache_defaults = http_defaults.cache.clone();
+    let http_config = http::HttpConfig {
+        connect_timeout: env_or(
+            "PHOTOJOURNALISM_CONNECT_TIMEOUT",
+            http_defaults.connect_timeout,
+        ),
+        request_timeout: env_or(
+            "PHOTOJOURNALISM_REQUEST_TIMEOUT",
+            http_defaults.request_timeout,
+        ),
+        max_body: env_or("PHOTOJOURNALISM_MAX_BODY_SIZE", http_defaults.max_body),
+        max_redirects: env_or("PHOTOJOURNALISM_MAX_REDIRECTS", http_defaults.max_redirects),
+        proxy: std::env::var("PHOTOJOURNALISM_HTTP_PROXY").ok(),
+        cache: cache::CacheConfig {
+            enabled: env_or("PHOTOJOURNALISM_HTTP_CACHE", cache_defaults.enabled),
+            dir: env_or("PHOTOJOURNALISM_CACHE_DIR", cache_defaults.dir),
+            max_size: env_or("PHOTOJOURNALISM_CACHE_MAX_SIZE", cache_defaults.max_size),
+            gc_interval: env_or(
+                "PHOTOJOURNALISM_CACHE_GC_INTERVAL",
+                cache_defaults.gc_interval,
+            ),
+        },
+    };
+    let client = http::HttpClient::new(&http_config)
+        .expect("could not build the http client, check 'PHOTOJOURNALISM_HTTP_PROXY'");
+
+    match client.cache() {
+        Some(disk_cache) => {
+            info!(
+                "caching http responses in '{}', up to {} bytes",
+                disk_cache.path().display(),
+                http_config.cache.max_size
+            );
+            let disk_cache = disk_cache.clone();
+            let gc_interval = http_config.cache.gc_interval;
+            tokio::spawn(async move {
+                cache::background(disk_cache, gc_interval).await;
+            });
+        }
+        None => info!("http response cache is turned off"),
+    }
+
+    // photos are kept in memory, unless a database is given to keep them across restarts
+    let feed_db: loader::FeedDb = match std::env::var("PHOTOJOURNALISM_DATABASE") {
+        Ok(path) => {
+            let store = SqliteStore::open(Path::new(&path))
+                .unwrap_or_else(|err| panic!("could not open database '{path}': {err}"));
+            info!("keeping photos in the database '{path}'");
+            Arc::new(store)
+        }
+        Err(_) => Arc::new(MemoryStore::default()),
+    };
+
+    // restore the photos saved at the last shutdown, so there is something to serve right away
+    // (unless the database already has them)
+    let snapshot_path = std::env::var("PHOTOJOURNALISM_SNAPSHOT")
+        .ok()
+        .map(PathBuf::from);
+    if let Some(path) = snapshot_path.clone() {
+        let db = feed_db.clone();
+        // the database and the snapshot are both read from disk, off the runtime
+        if let Err(err) = tokio::task::spawn_blocking(move || restore_snapshot(&db, &path)).await {
+            warn!("restoring the snapshot failed: {err}");
+        }
+    }
+    // the photos restored (or already in the database) are served until the first fetches finish
+    let phash_defaults = phash::PhashConfig::default();
+    let phash_config = phash::PhashConfig {
+        enabled: env_or("PHOTOJOURNALISM_PERCEPTUAL_HASH", phash_defaults.enabled),
+        max_distance: env_or("PHOTOJOURNALISM_HASH_DISTANCE", phash_defaults.max_distance),
+    };
+    let photo_index = match phash_config.enabled {
+        true => {
+            info!(
+                "merging photos whose images differ in up to {} bits",
+                phash_config.max_distance
+            );
+            index::SharedIndex::with_image_hashes(phash::ImageHashes::new(
+                phash_config.max_distance,
+            ))
+        }
+        false => index::SharedIndex::default(),
+    };
+    loader::publish(&photo_index, &feed_db).await;
+    let status_db = Arc::new(Mutex::new(HashMap::<String, loader::FeedStatus>::new()));
 
-    info!("fetching rss feeds every {fetch_interval} seconds");
+    info!(
+        "fetching rss feeds every {fetch_interval} seconds by default, within {}..{} seconds",
+        loader_config.min_interval, loader_config.max_interval
+    );
     let db = feed_db.clone();
+    let shared = photo_index.clone();
+    let status = status_db.clone();
+    let (list_sender, list_receiver) = watch::channel(feeds);
+    let catalog = list_receiver.clone();
+    let reload_interval = env_or("PHOTOJOURNALISM_FEED_LIST_CHECK", 30);
     tokio::spawn(async move {
-        loader::background(feeds, db, fetch_interval).await;
+        feeds::watch_feed_list(feed_path, list_sender, reload_interval).await;
+    });
+    let (stop_sender, stop_receiver) = watch::channel(false);
+    let snapshot_stop = stop_receiver.clone();
+    let loading = tokio::spawn(async move {
+        loader::background(
+            list_receiver,
+            db,
+            shared,
+            status,
+            client,
+            loader_config,
+            stop_receiver,
+        )
+        .await;
+    });
+    let snapshotting = snapshot_path.clone().map(|path| {
+        let db = feed_db.clone();
+        let snapshot_interval = env_or("PHOTOJOURNALISM_SNAPSHOT_INTERVAL", 300);
+        tokio::spawn(async move {
+            snapshot::background(db, path, snapshot_interval, snapshot_stop).await;
+        })
     });
 
     let address = std::env::var("PHOTOJOURNALISM_SERVER")
@@ -56,11 +212,101 @@ async fn main() -> std::io::Result<()> {
 
     let page_size = std::env::var("PHOTOJOURNALISM_PAGE_SIZE")
         .expect("env var 'PHOTOJOURNALISM_PAGE_SIZE' not defined");
+    #[allow(clippy::manual_unwrap_or)]
     let next_size = match page_size.parse() {
         Ok(i) => i,
         Err(_) => 8, // default
     };
 
     info!("web service running on {address}");
-    server::run(listener, feed_db, next_size, static_folder)?.await
+    let server = server::run(
+        listener,
+        photo_index,
+        status_db,
+        catalog,
+        next_size,
+        static_folder,
+    )?;
+    let handle = server.handle();
+    let mut serving = tokio::spawn(server);
+
+    // keep serving until told to stop, then stop fetching, save what was fetched, and only
+    // then stop serving, so the last requests still get the latest photos
+    let stopped = tokio::select! {
+        served = &mut serving => Some(served),
+        _ = terminated() => None,
+    };
+    info!("shutting down");
+    let _ = stop_sender.send(true);
+    if let Err(err) = loading.await {
+        warn!("loader did not stop cleanly: {err}");
+    }
+    // a periodic save still under way would write the same file as the last one
+    if let Some(snapshotting) = snapshotting
+        && let Err(err) = snapshotting.await
+    {
+        warn!("periodic snapshots did not stop cleanly: {err}");
+    }
+    save_snapshot(&feed_db, snapshot_path).await;
+    let served = match stopped {
+        Some(served) => served,
+        None => {
+            handle.stop(true).await;
+            serving.await
+        }
+    };
+    served.map_err(std::io::Error::other)?
+}
+
+/// Waits for SIGTERM (as sent by docker and systemd) or SIGINT (ctrl-c).
+async fn terminated() {
+    let mut term = match signal(SignalKind::terminate()) {
+        Ok(term) => term,
+        Err(err) => {
+            warn!("cannot listen for SIGTERM: {err}");
+            let _ = tokio::signal::ctrl_c().await;
+            return;
+        }
+    };
+    tokio::select! {
+        _ = term.recv() => {}
+        _ = tokio::signal::ctrl_c() => {}
+    }
+}
+
+/// Loads the photos saved at `path` into `db`, unless it has some already.
+fn restore_snapshot(db: &loader::FeedDb, path: &Path) {
+    if !db.counts().is_ok_and(|(feeds, _)| feeds == 0) {
+        return;
+    }
+    match snapshot::load(path) {
+        Ok(saved) => {
+            info!(
+                "restored {} photos of {} feeds from '{}', saved at {}",
+                saved.feeds.values().map(Vec::len).sum::<usize>(),
+                saved.feeds.len(),
+                path.display(),
+                saved.saved_at
+            );
+            for (url, photos) in saved.feeds {
+                if let Err(err) = db.upsert_feed(&url, photos) {
+                    warn!("cannot restore the photos of '{url}': {err}");
+                }
+            }
+        }
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+        Err(err) => warn!("starting empty, cannot restore '{}': {err}", path.display()),
+    }
+}
+
+async fn save_snapshot(db: &loader::FeedDb, path: Option<PathBuf>) {
+    if let Some(path) = path {
+        let db = db.clone();
+        let target = path.clone();
+        match tokio::task::spawn_blocking(move || snapshot::save(&db, &target)).await {
+            Ok(Ok(photos)) => info!("saved {photos} photos to '{}'", path.display()),
+            Ok(Err(err)) => warn!("could not save '{}': {err}", path.display()),
+            Err(err) => warn!("saving '{}' failed: {err}", path.display()),
+        }
+    }
 }
@@ -0,0 +1,183 @@
+use crate::feeds::{CatalogError, Feed};
+use quick_xml::events::{BytesDecl, BytesStart, BytesText, Event};
+use quick_xml::{Reader, Writer, XmlVersion};
+use std::collections::HashSet;
+use url::Url;
+
+fn attribute(element: &BytesStart, name: &str) -> Option<String> {
+    let value = element
+        .try_get_attribute(name)
+        .ok()??
+        .normalized_value(XmlVersion::Implicit1_0)
+        .ok()?
+        .trim()
+        .to_string();
+    (!value.is_empty()).then_some(value)
+}
+
+/// Parses an OPML 2.0 subscription list: every `outline` with an `xmlUrl` is a feed,
+/// named by its `title` (or `text`), and filed under the outlines it is nested in,
+/// the outermost one being its section and the others (plus its `category`) its tags.
+pub fn parse_opml(text: &str) -> Result<Vec<Feed>, CatalogError> {
+    let mut reader = Reader::from_str(text);
+    reader.config_mut().trim_text(true);
+
+    let mut is_opml = false;
+    // the title of each enclosing outline, or None for a feed with outlines of its own
+    let mut categories: Vec<Option<String>> = Vec::new();
+    let mut feeds: Vec<Feed> = Vec::new();
+    let mut seen: HashSet<String> = HashSet::new();
+    loop {
+        let event = reader.read_event().map_err(|err| {
+            CatalogError::at(text, reader.error_position() as usize, err.to_string())
+        })?;
+        let (element, nested) = match &event {
+            Event::Start(element) => (element, true),
+            Event::Empty(element) => (element, false),
+            Event::End(element) if element.name().as_ref() == b"outline" => {
+                categories.pop();
+                continue;
+            }
+            Event::Eof => break,
+            _ => continue,
+        };
+        match element.name().as_ref() {
+            b"opml" => is_opml = true,
+            b"outline" => {
+                let title = attribute(element, "title").or_else(|| attribute(element, "text"));
+                let Some(url) = attribute(element, "xmlUrl") else {
+                    if nested {
+                        categories.push(title);
+                    }
+                    continue;
+                };
+                if nested {
+                    categories.push(None);
+                }
+
+                let end = reader.buffer_position() as usize;
+                let start = text[..end.min(text.len())].rfind('<').unwrap_or_default();
+                if !Url::parse(&url)
+                    .is_ok_and(|parsed| ["http", "https"].contains(&parsed.scheme()))
+                {
+                    return Err(CatalogError::at(
+                        text,
+                        start,
+                        format!("invalid feed url '{url}'"),
+                    ));
+                }
+                // the same feed is often filed in more than one folder
+                if !seen.insert(url.clone()) {
+                    log::warn!("opml: ignoring '{url}', which is listed more than once");
+                    continue;
+                }
+
+                let mut filed = categories.iter().flatten().cloned();
+                let section = filed.next();
+                let mut tags: Vec<String> = filed.collect();
+                // e.g. category="/World/Europe,photography"
+                for tag in attribute(element, "category")
+                    .unwrap_or_default()
+                    .split([',', '/'])
+                    .map(str::trim)
+                {
+                    if !tag.is_empty() && !tags.iter().any(|t| t == tag) {
+                        tags.push(tag.to_string());
+                    }
+                }
+                feeds.push(Feed {
+                    // untitled feeds are exported with their url as the title
+                    name: title.filter(|title| *title != url),
+                    section,
+                    language: attribute(element, "language"),
+                    tags,
+                    ..Feed::new(&url)
+                });
+            }
+            _ => {}
+        }
+    }
+
+    if !is_opml {
+        return Err(CatalogError::at(
+            text,
+            0,
+            "not an opml document".to_string(),
+        ));
+    }
+    Ok(feeds)
+}
+
+fn write_outline(writer: &mut Writer<Vec<u8>>, feed: &Feed) -> std::io::Result<()> {
+    let title = feed.name.as_deref().unwrap_or(&feed.url);
+    let mut outline = writer
+        .create_element("outline")
+        .with_attribute(("type", "rss"))
+        .with_attribute(("text", title))
+        .with_attribute(("title", title))
+        .with_attribute(("xmlUrl", feed.url.as_str()));
+    if let Some(language) = &feed.language {
+        outline = outline.with_attribute(("language", language.as_str()));
+    }
+    if !feed.tags.is_empty() {
+        outline = outline.with_attribute(("category", feed.tags.join(",").as_str()));
+    }
+    outline.write_empty()?;
+    Ok(())
+}
+
+/// Writes the feeds as an OPML 2.0 subscription list, each section as an outline of
+/// its own; the interval, weight and headers of a feed have no place in OPML.
+pub fn to_opml(feeds: &[Feed]) -> std::io::Result<String> {
+    let mut sections: Vec<&str> = Vec::new();
+    for section in feeds.iter().filter_map(|feed| feed.section.as_deref()) {
+        if !sections.contains(&section) {
+            sections.push(section);
+        }
+    }
+
+    let mut writer = Writer::new_with_indent(Vec::new(), b' ', 2);
+    writer.write_event(Event::Decl(BytesDecl::new("1.0", Some("UTF-8"), None)))?;
+    writer
+        .create_element("opml")
+        .with_attribute(("version", "2.0"))
+        .write_inner_content(|writer| {
+            writer
+                .create_element("head")
+                .write_inner_content(|writer| {
+                    writer
+                        .create_element("title")
+                        .write_text_content(BytesText::new(env!("CARGO_PKG_NAME")))?;
+                    Ok(())
+                })?;
+            writer
+                .create_element("body")
+                .write_inner_content(|writer| {
+                    for section in &sections {
+                        writer
+                            .create_element("outline")
+                            .with_attribute(("text", *section))
+                            .with_attribute(("title", *section))
+                            .write_inner_content(|writer| {
+                                for feed in feeds
+                                    .iter()
+                                    .filter(|feed| feed.section.as_deref() == Some(section))
+                                {
+                                    write_outline(writer, feed)?;
+                                }
+                                Ok(())
+                            })?;
+                    }
+                    for feed in feeds.iter().filter(|feed| feed.section.is_none()) {
+                        write_outline(writer, feed)?;
+                    }
+                    Ok(())
+                })?;
+            Ok(())
+        })?;
+    String::from_utf8(writer.into_inner()).map_err(std::io::Error::other)
+}
+
+#[path = "opml_test.rs"]
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,87 @@
+use super::*;
+
+#[test]
+fn outlines_become_feeds_filed_by_category() {
+    let opml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<opml version="2.0">
+  <head><title>Photo desks</title></head>
+  <body>
+    <outline text="World">
+      <outline text="Europe">
+        <outline type="rss" text="France 24" xmlUrl="https://www.france24.com/en/rss" language="en" category="/news,photography"/>
+      </outline>
+      <outline type="rss" title="The Japan Times" text="JT" xmlUrl="https://www.japantimes.co.jp/feed/"/>
+    </outline>
+    <outline type="rss" text="Aeon &amp; Psyche" xmlUrl="https://aeon.co/feed.rss"/>
+    <outline text="Duplicates">
+      <outline type="rss" text="Aeon again" xmlUrl="https://aeon.co/feed.rss"/>
+    </outline>
+  </body>
+</opml>"#;
+    assert_eq!(
+        parse_opml(opml),
+        Ok(vec![
+            Feed {
+                name: Some("France 24".to_string()),
+                section: Some("World".to_string()),
+                language: Some("en".to_string()),
+                tags: vec![
+                    "Europe".to_string(),
+                    "news".to_string(),
+                    "photography".to_string()
+                ],
+                ..Feed::new("https://www.france24.com/en/rss")
+            },
+            Feed {
+                name: Some("The Japan Times".to_string()),
+                section: Some("World".to_string()),
+                ..Feed::new("https://www.japantimes.co.jp/feed/")
+            },
+            Feed {
+                name: Some("Aeon & Psyche".to_string()),
+                ..Feed::new("https://aeon.co/feed.rss")
+            },
+        ])
+    );
+}
+
+#[test]
+fn invalid_opml_is_reported_by_line() {
+    let bad_url = "<opml version=\"2.0\">\n<body>\n<outline text=\"x\" xmlUrl=\"feed.rss\"/>\n</body>\n</opml>";
+    assert_eq!(parse_opml(bad_url).map_err(|err| err.line), Err(3));
+
+    let not_opml = "<rss version=\"2.0\"><channel></channel></rss>";
+    assert!(parse_opml(not_opml).is_err());
+}
+
+#[test]
+fn exported_feeds_import_back() {
+    let feeds = vec![
+        Feed {
+            name: Some("France 24".to_string()),
+            section: Some("world".to_string()),
+            language: Some("en".to_string()),
+            tags: vec!["news".to_string()],
+            // neither of which survives the trip
+            interval: Some(1800),
+            weight: 2,
+            ..Feed::new("https://www.france24.com/en/rss")
+        },
+        Feed::new("https://aeon.co/feed.rss"),
+    ];
+    let opml = to_opml(&feeds).unwrap();
+    assert!(opml.starts_with(r#"<?xml version="1.0" encoding="UTF-8"?>"#));
+
+    let imported = parse_opml(&opml).unwrap();
+    assert_eq!(
+        imported,
+        vec![
+            Feed {
+                interval: None,
+                weight: 1,
+                ..feeds[0].clone()
+            },
+            feeds[1].clone(),
+        ]
+    );
+}
@@ -1,11 +1,18 @@
-use http_cache_reqwest::{CACacheManager, Cache, CacheMode, HttpCache, HttpCacheOptions};
-use reqwest::Client;
-use reqwest::header::USER_AGENT;
-use reqwest_middleware::ClientBuilder;
-use rss::Channel;
+use crate::canonical::{canonicalize, canonicalize_media};
+use crate::error::FeedError;
+use crate::feeds::Feed;
+use crate::http::{Fetched, HttpClient, Validators};
+use chrono::DateTime;
+use rss::extension::Extension;
+use rss::extension::syndication::UpdatePeriod;
+use rss::{Channel, Item};
 use scraper::{Html, Selector};
 use serde::{Deserialize, Serialize};
-use std::{env::temp_dir, error::Error, path::PathBuf};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::BufReader;
+use url::Url;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NewsPhoto {
@@ -13,6 +20,201 @@ pub struct NewsPhoto {
     pub story_url: String,
     pub description: Option<String>,
     pub credit: Option<String>,
+    #[serde(default, skip_serializing_if = "MediaKind::is_photo")]
+    pub media: MediaKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub video_url: Option<String>,
+    // seconds since the unix epoch when the photo was first and last found in its feed
+    #[serde(default, skip_serializing_if = "is_unset")]
+    pub first_seen: u64,
+    #[serde(default, skip_serializing_if = "is_unset")]
+    pub last_seen: u64,
+    // seconds since the unix epoch, from <pubDate> or <dc:date>
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub published: Option<u64>,
+    // seconds since the unix epoch after which the photo is too old to be served
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+    // every feed the photo appeared in, once duplicates across feeds are merged
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub feeds: Vec<String>,
+    #[serde(skip)]
+    pub provenance: Provenance,
+}
+
+fn is_unset(secs: &u64) -> bool {
+    *secs == 0
+}
+
+/// Whether the image is a news photo proper, or the poster frame of a video.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MediaKind {
+    #[default]
+    Photo,
+    Video,
+}
+
+impl MediaKind {
+    pub fn is_photo(&self) -> bool {
+        *self == MediaKind::Photo
+    }
+}
+
+/// Records which part of the feed item each `NewsPhoto` field was taken from,
+/// e.g. `image_url` from `media:content[2]`, so that extraction bugs can be traced.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Provenance {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub story_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub credit: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub video_url: Option<String>,
+    // image candidates which were skipped as ignoreable (tracking pixels)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ignored: Vec<String>,
+}
+
+/// Why a feed item did not produce a `NewsPhoto`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rejection {
+    NoLink,
+    NoImage,
+    IgnoredUrl,
+    // older than the maximum age of photos from the feed
+    Expired,
+}
+
+/// A summary of what `get_photos` did with each item in a feed.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ExtractionReport {
+    pub items: usize,
+    pub accepted: usize,
+    pub rejected: BTreeMap<Rejection, usize>,
+    pub warnings: Vec<String>,
+    #[serde(default, skip_serializing_if = "RefreshHint::is_empty")]
+    pub refresh: RefreshHint,
+}
+
+const DAYS: [&str; 7] = [
+    "sunday",
+    "monday",
+    "tuesday",
+    "wednesday",
+    "thursday",
+    "friday",
+    "saturday",
+];
+
+/// What a channel says about how often it is worth fetching, from its
+/// `<ttl>`, `<skipHours>`, `<skipDays>` and `sy:updatePeriod`/`sy:updateFrequency`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RefreshHint {
+    // in seconds (the feed gives it in minutes)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u64>,
+    // in seconds, the update period divided by its frequency
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub update_period: Option<u64>,
+    // hours of the day (gmt) during which the feed does not change
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skip_hours: Vec<u8>,
+    // lowercased names of the days on which the feed does not change
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skip_days: Vec<String>,
+}
+
+impl RefreshHint {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// The longer of the ttl and the update period, if the channel gives either.
+    pub fn interval(&self) -> Option<u64> {
+        match (self.ttl, self.update_period) {
+            (Some(ttl), Some(period)) => Some(ttl.max(period)),
+            (ttl, period) => ttl.or(period),
+        }
+    }
+
+    fn skipped(&self, at: u64) -> bool {
+        let hour = (at / 3600 % 24) as u8;
+        // the unix epoch fell on a thursday
+        let day = DAYS[((at / 86400 + 4) % 7) as usize];
+        self.skip_hours.contains(&hour) || self.skip_days.iter().any(|d| d == day)
+    }
+
+    /// Moves `at` (seconds since the unix epoch) forward to the start of the first
+    /// hour which the channel does not ask to be skipped.
+    pub fn skip(&self, at: u64) -> u64 {
+        let mut next = at;
+        for _ in 0..(24 * 7) {
+            if !self.skipped(next) {
+                return next;
+            }
+            next = (next / 3600 + 1).saturating_mul(3600);
+        }
+        // every hour of the week is skipped, which is nonsense, so ignore it
+        at
+    }
+}
+
+fn refresh_hint(c: &Channel) -> RefreshHint {
+    let update_period = c.syndication_ext().map(|sy| {
+        let period = match sy.period() {
+            UpdatePeriod::Hourly => 3600,
+            UpdatePeriod::Daily => 86400,
+            UpdatePeriod::Weekly => 7 * 86400,
+            UpdatePeriod::Monthly => 30 * 86400,
+            UpdatePeriod::Yearly => 365 * 86400,
+        };
+        period / u64::from(sy.frequency().max(1))
+    });
+    RefreshHint {
+        ttl: c
+            .ttl()
+            .and_then(|ttl| ttl.trim().parse::<u64>().ok())
+            // too large to be meant, so left for LoaderConfig::interval_for to bound
+            .and_then(|minutes| minutes.checked_mul(60)),
+        update_period,
+        skip_hours: c
+            .skip_hours()
+            .iter()
+            .filter_map(|hour| hour.trim().parse::<u8>().ok())
+            .map(|hour| hour % 24)
+            .collect(),
+        skip_days: c
+            .skip_days()
+            .iter()
+            .map(|day| day.trim().to_lowercase())
+            .filter(|day| DAYS.contains(&day.as_str()))
+            .collect(),
+    }
+}
+
+impl ExtractionReport {
+    pub(crate) fn reject(&mut self, reason: Rejection) {
+        *self.rejected.entry(reason).or_insert(0) += 1;
+    }
+}
+
+#[derive(Serialize)]
+struct DebugPhoto<'a> {
+    #[serde(flatten)]
+    photo: &'a NewsPhoto,
+    provenance: &'a Provenance,
+}
+
+impl Default for NewsPhoto {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl NewsPhoto {
@@ -23,6 +225,29 @@ impl NewsPhoto {
             story_url: Self::EMPTY,
             description: None,
             credit: None,
+            media: MediaKind::Photo,
+            video_url: None,
+            first_seen: 0,
+            last_seen: 0,
+            published: None,
+            expires_at: None,
+            feeds: Vec::new(),
+            provenance: Provenance::default(),
+        }
+    }
+
+    /// Whether the photo has outlived the maximum age of photos from its feed.
+    pub fn expired(&self, at: u64) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= at)
+    }
+
+    /// What identifies the photo from one fetch of its feed to the next: the
+    /// (canonical) url of its story, or failing that, of the image itself.
+    pub fn identity(&self) -> &str {
+        if self.story_url != Self::EMPTY {
+            &self.story_url
+        } else {
+            &self.image_url
         }
     }
 
@@ -46,53 +271,178 @@ impl NewsPhoto {
     pub fn as_json(&self) -> Result<String, serde_json::Error> {
         serde_json::to_string(&self)
     }
+
+    /// Same as `as_json`, but with the provenance of each field included.
+    pub fn as_debug_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(&DebugPhoto {
+            photo: self,
+            provenance: &self.provenance,
+        })
+    }
+
+    fn set_image_url(&mut self, url: &str, source: String) {
+        self.image_url = url.to_string();
+        self.provenance.image_url = Some(source);
+    }
+
+    fn set_video_url(&mut self, url: &str, source: String) {
+        self.video_url = Some(url.to_string());
+        self.media = MediaKind::Video;
+        self.provenance.video_url = Some(source);
+    }
+
+    fn offer_image_url(&mut self, url: &str, source: String) {
+        if is_video(url) {
+            self.set_video_url(url, source)
+        } else if ignore(url) {
+            self.provenance.ignored.push(source)
+        } else {
+            self.set_image_url(url, source)
+        }
+    }
+
+    fn rejection(&self) -> Option<Rejection> {
+        if self.story_url == Self::EMPTY {
+            Some(Rejection::NoLink)
+        } else if self.image_url != Self::EMPTY {
+            None
+        } else if self.provenance.ignored.is_empty() {
+            Some(Rejection::NoImage)
+        } else {
+            Some(Rejection::IgnoredUrl)
+        }
+    }
+
+    fn set_story_url(&mut self, url: &str, source: String) {
+        self.story_url = url.to_string();
+        self.provenance.story_url = Some(source);
+    }
+
+    fn set_description(&mut self, text: &str, source: String) {
+        self.description = Some(text.to_string());
+        self.provenance.description = Some(source);
+    }
+
+    fn set_credit(&mut self, text: &str, source: String) {
+        self.credit = Some(text.to_string());
+        self.provenance.credit = Some(source);
+    }
 }
 
-const IGNOREABLE: [&'static str; 3] = [".mp4", ".mov", "npr-rss-pixel.png"];
+const IGNOREABLE: [&str; 1] = ["npr-rss-pixel.png"];
 
-fn ignore(url: &String) -> bool {
+fn ignore(url: &str) -> bool {
     IGNOREABLE.iter().any(|ext| url.contains(ext))
 }
 
-fn user_agent() -> String {
-    format!(
-        "{}/{} +http://github.com/dpapathanasiou/photojournalism",
-        env!("CARGO_PKG_NAME"),
-        env!("CARGO_PKG_VERSION")
-    )
-}
+const VIDEOS: [&str; 4] = ["mp4", "mov", "m3u8", "webm"];
 
-fn set_cache_manager() -> CACacheManager {
-    // prefer the system tmp folder as opposed to `./` which is the CACacheManager default
-    let mut manager = CACacheManager::default();
-    let tmp_path: PathBuf = [temp_dir(), "http-cacache".into()].iter().collect();
-    manager.path = tmp_path;
-    manager
+// by the extension of the file the url points to, so e.g. www.movies.com/poster.jpg is no video
+fn is_video(url: &str) -> bool {
+    let path = match Url::parse(url) {
+        Ok(parsed) => parsed.path().to_string(),
+        Err(_) => url.split(['?', '#']).next().unwrap_or_default().to_string(),
+    };
+    let file = path.rsplit('/').next().unwrap_or_default();
+    file.rsplit_once('.')
+        .is_some_and(|(_, ext)| VIDEOS.iter().any(|video| ext.eq_ignore_ascii_case(video)))
 }
 
-async fn load_feed(url: &str) -> std::result::Result<Channel, Box<dyn Error>> {
-    let client = ClientBuilder::new(Client::new())
-        .with(Cache(HttpCache {
-            mode: CacheMode::Default,
-            manager: set_cache_manager(),
-            options: HttpCacheOptions::default(),
-        }))
-        .build();
-    let content = client
-        .get(url)
-        .header(USER_AGENT, user_agent())
-        .send()
-        .await?
-        .bytes()
-        .await?;
+fn is_video_medium(medium: &Extension) -> bool {
+    // <media:content medium="video"> or <media:content type="video/mp4">
+    medium.attrs().get("medium").is_some_and(|m| m == "video")
+        || medium
+            .attrs()
+            .get("type")
+            .is_some_and(|t| t.starts_with("video/"))
+}
 
+async fn load_feed(client: &HttpClient, url: &str) -> Result<Channel, FeedError> {
+    let content = client.get(url).await?;
     let channel = Channel::read_from(&content[..])?;
     Ok(channel)
 }
 
-fn get_photos(c: Channel) -> Vec<NewsPhoto> {
+fn scan_html(photo: &mut NewsPhoto, html: &str, field: &str) {
+    // look for <img> tags embedded in html (typically inside CDATA blocks)
+    let fragment = Html::parse_fragment(html);
+    match Selector::parse(r#"img"#) {
+        Ok(selector) => {
+            for (i, elem) in fragment.select(&selector).enumerate() {
+                if let Some(img_url) = elem.value().attr("src") {
+                    photo.offer_image_url(img_url, format!("{field} img[{i}] src"))
+                }
+                if let Some(alt_text) = elem.value().attr("alt")
+                    && !alt_text.is_empty()
+                {
+                    photo.set_description(alt_text, format!("{field} img[{i}] alt"))
+                }
+            }
+        }
+        Err(_) => photo.set_description(html, field.to_string()),
+    }
+}
+
+fn canonical_link(item: &Item) -> Option<(String, String)> {
+    // <atom:link rel="canonical" href="..."/>
+    if let Some(links) = item
+        .extensions()
+        .get("atom")
+        .and_then(|ext| ext.get("link"))
+    {
+        for (i, link) in links.iter().enumerate() {
+            if link
+                .attrs()
+                .get("rel")
+                .is_some_and(|rel| rel == "canonical")
+                && let Some(href) = link.attrs().get("href")
+            {
+                return Some((href.to_string(), format!("atom:link[{i}] canonical")));
+            }
+        }
+    }
+
+    // <link rel="canonical" href="..."> embedded in the item's html
+    for (field, html) in [
+        ("content:encoded", item.content()),
+        ("description", item.description()),
+    ] {
+        if let Some(html) = html
+            && let Ok(selector) = Selector::parse(r#"link[rel="canonical"]"#)
+            && let Some(elem) = Html::parse_fragment(html).select(&selector).next()
+            && let Some(href) = elem.value().attr("href")
+        {
+            return Some((href.to_string(), format!("{field} link canonical")));
+        }
+    }
+    None
+}
+
+fn published(item: &Item) -> Result<Option<u64>, String> {
+    // <pubDate> is RFC 2822, <dc:date> is ISO 8601
+    let date = match item.pub_date() {
+        Some(date) => DateTime::parse_from_rfc2822(date.trim())
+            .map_err(|err| format!("unreadable pubDate '{date}' ({err})"))?,
+        None => match item.dublin_core_ext().and_then(|dc| dc.dates().first()) {
+            Some(date) => DateTime::parse_from_rfc3339(date.trim())
+                .map_err(|err| format!("unreadable dc:date '{date}' ({err})"))?,
+            None => return Ok(None),
+        },
+    };
+    Ok(u64::try_from(date.timestamp()).ok())
+}
+
+fn get_photos(c: Channel) -> (Vec<NewsPhoto>, ExtractionReport) {
     let mut results = Vec::new();
-    for item in c.items() {
+    let mut report = ExtractionReport {
+        items: c.items().len(),
+        refresh: refresh_hint(&c),
+        ..ExtractionReport::default()
+    };
+    if c.items().is_empty() {
+        report.warnings.push("feed contains no items".to_string());
+    }
+    for (n, item) in c.items().iter().enumerate() {
         let mut photo = NewsPhoto::new();
 
         /*
@@ -104,86 +454,49 @@ fn get_photos(c: Channel) -> Vec<NewsPhoto> {
           overwritten by the expected definitions, if they exist.
         */
 
-        if item.link().is_some() {
-            photo.story_url = item.link().clone().unwrap().to_string()
+        if let Some(link) = item.link() {
+            photo.set_story_url(link, "link".to_string())
         }
 
-        if item.title().is_some() {
-            photo.description = Some(item.title().clone().unwrap().to_string())
+        if let Some(title) = item.title() {
+            photo.set_description(title, "title".to_string())
         }
 
         if let Some(content) = item.content() {
-            let fragment = Html::parse_fragment(content);
-            match Selector::parse(r#"img"#) {
-                Ok(selector) => {
-                    for elem in fragment.select(&selector) {
-                        if elem.value().attr("src").is_some() {
-                            let img_url = elem.value().attr("src").clone().unwrap().to_string();
-                            if !ignore(&img_url) {
-                                photo.image_url = img_url
-                            }
-                        }
-                        if let Some(alt_text) = elem.value().attr("alt") {
-                            if alt_text.len() > 0 {
-                                photo.description = Some(alt_text.to_string())
-                            }
-                        }
-                    }
-                }
-                Err(_) => photo.description = Some(content.to_string()),
-            }
+            scan_html(&mut photo, content, "content:encoded");
         }
 
         if let Some(desc) = item.description() {
-            let fragment = Html::parse_fragment(desc);
-            match Selector::parse(r#"img"#) {
-                Ok(selector) => {
-                    for elem in fragment.select(&selector) {
-                        if elem.value().attr("src").is_some() {
-                            let img_url = elem.value().attr("src").clone().unwrap().to_string();
-                            if !ignore(&img_url) {
-                                photo.image_url = img_url
-                            }
-                        }
-                        if let Some(alt_text) = elem.value().attr("alt") {
-                            if alt_text.len() > 0 {
-                                photo.description = Some(alt_text.to_string())
-                            }
-                        }
-                    }
-                }
-                Err(_) => photo.description = Some(desc.to_string()),
-            }
+            scan_html(&mut photo, desc, "description");
         }
 
         if let Some(enc) = item.enclosure() {
             if enc.mime_type().starts_with("image/") {
-                let img_url = enc.url().to_string();
-                if !ignore(&img_url) {
-                    photo.image_url = img_url
-                }
+                photo.offer_image_url(enc.url(), "enclosure".to_string())
+            } else if enc.mime_type().starts_with("video/") {
+                photo.set_video_url(enc.url(), "enclosure".to_string())
             }
         }
 
-        if let Some(src) = item.source() {
-            if src.title().is_some() {
-                photo.credit = Some(src.title().clone().unwrap().to_string())
-            }
+        if let Some(src) = item.source()
+            && let Some(title) = src.title()
+        {
+            photo.set_credit(title, "source".to_string())
         }
 
         if let Some(dc) = item.dublin_core_ext() {
-            photo.credit = Some(dc.creators().join(", "))
+            photo.set_credit(&dc.creators().join(", "), "dc:creator".to_string())
         }
 
         for (extension_key, extension_map) in item.extensions() {
-            if extension_key == "atom" {
-                if extension_map.contains_key("link") {
-                    for medium in extension_map.get("link").unwrap() {
-                        if medium.name() == "atom:link" {
-                            for (key, val) in medium.attrs() {
-                                if key == "href" {
-                                    photo.story_url = val.to_string()
-                                }
+            if extension_key == "atom"
+                && let Some(links) = extension_map.get("link")
+            {
+                for (i, medium) in links.iter().enumerate() {
+                    if medium.name() == "atom:link" {
+                        for (key, val) in medium.attrs() {
+                            if key == "href" {
+                                photo.set_story_url(val, format!("atom:link[{i}]"))
                             }
                         }
                     }
@@ -191,27 +504,37 @@ fn get_photos(c: Channel) -> Vec<NewsPhoto> {
             }
 
             if extension_key == "media" {
-                if extension_map.contains_key("thumbnail") {
-                    for medium in extension_map.get("thumbnail").unwrap() {
+                if let Some(thumbnails) = extension_map.get("thumbnail") {
+                    for (i, medium) in thumbnails.iter().enumerate() {
                         if medium.name() == "media:thumbnail" {
                             for (key, val) in medium.attrs() {
                                 if key == "url" {
-                                    if !ignore(&val) {
-                                        photo.image_url = val.to_string()
-                                    }
+                                    photo.offer_image_url(val, format!("media:thumbnail[{i}]"))
                                 }
                             }
                         }
                     }
                 }
 
-                if extension_map.contains_key("content") {
-                    for medium in extension_map.get("content").unwrap() {
-                        if medium.name() == "media:content" {
-                            for (key, val) in medium.attrs() {
-                                if key == "url" {
-                                    if !ignore(&val) {
-                                        photo.image_url = val.to_string()
+                if let Some(contents) = extension_map.get("content") {
+                    for (i, medium) in contents.iter().enumerate() {
+                        if medium.name() == "media:content"
+                            && let Some(url) = medium.attrs().get("url")
+                        {
+                            let source = format!("media:content[{i}]");
+                            if !is_video_medium(medium) {
+                                photo.offer_image_url(url, source);
+                                continue;
+                            }
+                            photo.set_video_url(url, source.clone());
+                            // the poster frame of a video may be nested inside its content
+                            if let Some(posters) = medium.children().get("thumbnail") {
+                                for (j, poster) in posters.iter().enumerate() {
+                                    if let Some(poster_url) = poster.attrs().get("url") {
+                                        photo.offer_image_url(
+                                            poster_url,
+                                            format!("{source} media:thumbnail[{j}]"),
+                                        )
                                     }
                                 }
                             }
@@ -219,41 +542,106 @@ fn get_photos(c: Channel) -> Vec<NewsPhoto> {
                     }
                 }
 
-                if extension_map.contains_key("credit") {
-                    for medium in extension_map.get("credit").unwrap() {
-                        if medium.name() == "media:credit" && medium.value().is_some() {
-                            photo.credit = Some(medium.value().unwrap().to_string())
+                if let Some(credits) = extension_map.get("credit") {
+                    for (i, medium) in credits.iter().enumerate() {
+                        if medium.name() == "media:credit"
+                            && let Some(credit) = medium.value()
+                        {
+                            photo.set_credit(credit, format!("media:credit[{i}]"))
                         }
                     }
                 }
 
-                if extension_map.contains_key("description") {
-                    for medium in extension_map.get("description").unwrap() {
-                        if medium.name() == "media:description" && medium.value().is_some() {
-                            photo.description = Some(medium.value().unwrap().to_string())
+                if let Some(descriptions) = extension_map.get("description") {
+                    for (i, medium) in descriptions.iter().enumerate() {
+                        if medium.name() == "media:description"
+                            && let Some(description) = medium.value()
+                        {
+                            photo.set_description(description, format!("media:description[{i}]"))
                         }
                     }
                 }
             }
         }
 
-        if photo.valid() {
-            results.push(photo)
+        if let Some((url, source)) = canonical_link(item) {
+            photo.set_story_url(&url, source)
+        }
+
+        photo.story_url = canonicalize(&photo.story_url);
+        photo.image_url = canonicalize_media(&photo.image_url);
+        if let Some(video_url) = &photo.video_url {
+            photo.video_url = Some(canonicalize_media(video_url));
+        }
+
+        match photo.rejection() {
+            Some(reason) => report.reject(reason),
+            None => {
+                match published(item) {
+                    Ok(published) => photo.published = published,
+                    Err(warning) => report.warnings.push(format!("item {n}: {warning}")),
+                }
+                if !photo.image_url.starts_with("http") {
+                    report.warnings.push(format!(
+                        "item {n}: image url '{}' is not absolute",
+                        photo.image_url
+                    ))
+                }
+                if photo.credit.as_ref().is_some_and(|c| c.trim().is_empty()) {
+                    report.warnings.push(format!(
+                        "item {n}: empty credit from {:?}",
+                        photo.provenance.credit
+                    ))
+                }
+                report.accepted += 1;
+                results.push(photo)
+            }
         }
     }
-    results
+    (results, report)
+}
+
+pub async fn get_photos_from_feed(
+    client: &HttpClient,
+    url: &str,
+) -> Result<(Vec<NewsPhoto>, ExtractionReport), FeedError> {
+    let channel = load_feed(client, url).await?;
+    Ok(get_photos(channel))
 }
 
-pub async fn get_photos_from_feed(url: &str) -> Vec<NewsPhoto> {
-    match load_feed(url).await {
-        Ok(channel) => get_photos(channel),
-        Err(err) => {
-            log::error!("could not access RSS feed at '{url}': {:#?}", err);
-            Vec::new()
+/// Like `get_photos_from_feed`, but skips the download and the parse when the feed has
+/// not changed since `validators` were recorded, producing `None` instead.
+pub async fn get_photos_if_modified(
+    client: &HttpClient,
+    feed: &Feed,
+    validators: &Validators,
+) -> Result<Option<(Vec<NewsPhoto>, ExtractionReport, Validators)>, FeedError> {
+    match client
+        .get_if_modified(&feed.url, validators, &feed.headers)
+        .await?
+    {
+        Fetched::Modified { body, validators } => {
+            let channel = Channel::read_from(&body[..])?;
+            let (photos, report) = get_photos(channel);
+            Ok(Some((photos, report, validators)))
         }
+        Fetched::NotModified => Ok(None),
     }
 }
 
+pub async fn inspect_feed(
+    client: &HttpClient,
+    source: &str,
+) -> Result<(Vec<NewsPhoto>, ExtractionReport), Box<dyn Error>> {
+    // accept either a live feed url or a local file (e.g. one of the test fixtures)
+    let channel = if source.starts_with("http://") || source.starts_with("https://") {
+        load_feed(client, source).await?
+    } else {
+        Channel::read_from(BufReader::new(File::open(source)?))?
+    };
+    Ok(get_photos(channel))
+}
+
 #[path = "parser_test.rs"]
 #[cfg(test)]
 mod tests;
@@ -5,6 +5,7 @@ use std::fs::read_to_string;
 use std::path::Path;
 use std::str::FromStr;
 
+#[allow(clippy::manual_ok_err)]
 fn load_fixture(filename: String) -> Option<String> {
     if let Ok(path) = current_dir() {
         let fixture_file = format!("tests/fixtures/{filename}");
@@ -34,7 +35,7 @@ fn parser_can_find_images_in_cdata() {
     let channel = Channel::from_str(&feed.unwrap());
     assert!(channel.is_ok());
 
-    let results = get_photos(channel.unwrap());
+    let (results, _) = get_photos(channel.unwrap());
     assert_eq!(results.len(), 20);
 }
 
@@ -52,11 +53,12 @@ fn parser_ignores_feeds_without_images() {
     let channel = Channel::from_str(&feed.unwrap());
     assert!(channel.is_ok());
 
-    let results = get_photos(channel.unwrap());
+    let (results, _) = get_photos(channel.unwrap());
     assert_eq!(results.len(), 0);
 }
 
 #[test]
+#[allow(clippy::manual_filter_map)]
 fn parser_finds_dublin_core_correctly() {
     /*
     Quanta Magazine's feed uses Dublin Core for photo credits, inside CDATA blocks,
@@ -69,7 +71,7 @@ fn parser_finds_dublin_core_correctly() {
     let channel = Channel::from_str(&feed.unwrap());
     assert!(channel.is_ok());
 
-    let results = get_photos(channel.unwrap());
+    let (results, _) = get_photos(channel.unwrap());
     assert_eq!(results.len(), 5);
 
     let expected_credits = vec![
@@ -102,7 +104,7 @@ fn parser_finds_image_content_when_given_both_thumbnails_and_content() {
     let channel = Channel::from_str(&feed.unwrap());
     assert!(channel.is_ok());
 
-    let results = get_photos(channel.unwrap());
+    let (results, _) = get_photos(channel.unwrap());
     assert_eq!(results.len(), 30);
 
     let expected_images = vec![
@@ -146,6 +148,7 @@ fn parser_finds_image_content_when_given_both_thumbnails_and_content() {
 }
 
 #[test]
+#[allow(clippy::manual_filter_map)]
 fn parser_finds_image_description_when_available() {
     /*
     The NY Times feed is a good example of well-executed content, which follows
@@ -163,7 +166,7 @@ fn parser_finds_image_description_when_available() {
     let channel = Channel::from_str(&feed.unwrap());
     assert!(channel.is_ok());
 
-    let results = get_photos(channel.unwrap());
+    let (results, _) = get_photos(channel.unwrap());
     assert_eq!(results.len(), 20);
 
     let expected_descriptions = vec![
@@ -213,7 +216,7 @@ fn parser_ignores_tracking_images() {
     let channel = Channel::from_str(&feed.unwrap());
     assert!(channel.is_ok());
 
-    let results = get_photos(channel.unwrap());
+    let (results, _) = get_photos(channel.unwrap());
     assert_eq!(results.len(), 14);
 
     let expected_images = vec![
@@ -239,3 +242,273 @@ fn parser_ignores_tracking_images() {
         .collect();
     assert_eq!(actual_images, expected_images);
 }
+
+#[test]
+fn parser_records_field_provenance() {
+    /*
+    Every field of a NewsPhoto can come from several places in a feed item,
+    so the parser records where each one was found.
+
+    The NY Times feed has the story link in both <link> and <atom:link>, and
+    the image description in <media:description>, which wins over the <title>.
+     */
+
+    let feed = load_fixture("nytimes.xml".to_string());
+    assert!(feed.is_some());
+
+    let channel = Channel::from_str(&feed.unwrap());
+    assert!(channel.is_ok());
+
+    let (results, _) = get_photos(channel.unwrap());
+    let first = &results[0];
+    assert_eq!(
+        first.provenance,
+        Provenance {
+            image_url: Some("media:content[0]".to_string()),
+            story_url: Some("atom:link[0]".to_string()),
+            description: Some("media:description[0]".to_string()),
+            credit: Some("media:credit[0]".to_string()),
+            ..Provenance::default()
+        }
+    );
+
+    // Aeon embeds the image as html inside the description CDATA block
+    let feed = load_fixture("aeon.xml".to_string());
+    let (results, _) = get_photos(Channel::from_str(&feed.unwrap()).unwrap());
+    assert_eq!(
+        results[0].provenance.image_url,
+        Some("description img[0] src".to_string())
+    );
+    assert_eq!(results[0].provenance.story_url, Some("link".to_string()));
+}
+
+#[test]
+fn parser_reports_why_items_were_rejected() {
+    /*
+    Items which cannot be turned into a NewsPhoto are counted in the
+    extraction report by the reason they were dropped, so that a feed
+    yielding a handful of photos from many items stands out.
+     */
+
+    let feed = load_fixture("bbc.xml".to_string());
+    let (results, report) = get_photos(Channel::from_str(&feed.unwrap()).unwrap());
+    assert_eq!(results.len(), 0);
+    assert_eq!(report.items, 16);
+    assert_eq!(report.accepted, 0);
+    assert_eq!(report.rejected.get(&Rejection::NoImage), Some(&16));
+
+    let feed = load_fixture("nytimes.xml".to_string());
+    let (results, report) = get_photos(Channel::from_str(&feed.unwrap()).unwrap());
+    assert_eq!(report.items, 25);
+    assert_eq!(report.accepted, results.len());
+    assert_eq!(
+        report.rejected.values().sum::<usize>(),
+        report.items - report.accepted
+    );
+
+    // an item whose only image is a tracking pixel is rejected for its ignored url
+    let tracked = r#"<rss version="2.0" xmlns:media="http://search.yahoo.com/mrss/"><channel>
+        <title>t</title><link>https://example.com</link><description>d</description>
+        <item>
+          <title>A tracked story</title>
+          <link>https://example.com/story</link>
+          <media:content url="https://example.com/npr-rss-pixel.png" />
+        </item>
+        <item><title>No link</title></item>
+      </channel></rss>"#;
+    let (results, report) = get_photos(Channel::from_str(tracked).unwrap());
+    assert_eq!(results.len(), 0);
+    assert_eq!(report.rejected.get(&Rejection::IgnoredUrl), Some(&1));
+    assert_eq!(report.rejected.get(&Rejection::NoLink), Some(&1));
+}
+
+#[test]
+fn parser_keeps_video_poster_frames() {
+    /*
+    Video items usually come with a poster frame, either as a separate
+    <media:thumbnail> or nested inside the video's <media:content>, which
+    is a news photo in its own right.
+
+    This test confirms the poster is kept as the image, and the video
+    itself is recorded alongside it, while a video without any poster
+    frame is still rejected for having no image.
+     */
+
+    let videos = r#"<rss version="2.0" xmlns:media="http://search.yahoo.com/mrss/"><channel>
+        <title>t</title><link>https://example.com</link><description>d</description>
+        <item>
+          <title>Separate poster</title>
+          <link>https://example.com/one</link>
+          <media:thumbnail url="https://example.com/one.jpg" />
+          <media:content url="https://example.com/one.mp4" />
+        </item>
+        <item>
+          <title>Nested poster</title>
+          <link>https://example.com/two</link>
+          <media:content url="https://example.com/two/master" medium="video" type="application/x-mpegURL">
+            <media:thumbnail url="https://example.com/two.jpg" />
+          </media:content>
+        </item>
+        <item>
+          <title>Just a photo</title>
+          <link>https://example.com/three</link>
+          <media:content url="https://example.com/three.jpg" medium="image" />
+        </item>
+        <item>
+          <title>No poster</title>
+          <link>https://example.com/four</link>
+          <enclosure url="https://example.com/four.mov" length="0" type="video/quicktime" />
+        </item>
+      </channel></rss>"#;
+
+    let (results, report) = get_photos(Channel::from_str(videos).unwrap());
+    assert_eq!(results.len(), 3);
+    assert_eq!(report.rejected.get(&Rejection::NoImage), Some(&1));
+
+    assert_eq!(results[0].media, MediaKind::Video);
+    assert_eq!(results[0].image_url, "https://example.com/one.jpg");
+    assert_eq!(
+        results[0].video_url,
+        Some("https://example.com/one.mp4".to_string())
+    );
+
+    assert_eq!(results[1].media, MediaKind::Video);
+    assert_eq!(results[1].image_url, "https://example.com/two.jpg");
+    assert_eq!(
+        results[1].provenance.image_url,
+        Some("media:content[0] media:thumbnail[0]".to_string())
+    );
+
+    assert_eq!(results[2].media, MediaKind::Photo);
+    assert_eq!(results[2].video_url, None);
+}
+
+#[test]
+fn videos_are_told_apart_by_the_extension_of_their_path() {
+    assert!(is_video("https://example.com/clips/one.mp4"));
+    assert!(is_video("https://example.com/clips/one.MOV?token=abc"));
+    assert!(is_video("https://example.com/live/master.m3u8#t=10"));
+    assert!(!is_video("https://www.movies.com/images/poster.jpg"));
+    assert!(!is_video("https://example.com/image.jpg?source=clip.mp4"));
+    assert!(!is_video("https://example.com/mp4/still.png"));
+}
+
+#[test]
+fn parser_canonicalizes_story_and_image_urls() {
+    /*
+    Aeon appends a utm_source parameter to every story link, which the
+    parser strips, while keeping the sizing parameters of its images.
+
+    An explicitly canonical link always wins over the item's <link>.
+     */
+
+    let feed = load_fixture("aeon.xml".to_string());
+    let (results, _) = get_photos(Channel::from_str(&feed.unwrap()).unwrap());
+    assert_eq!(
+        results[0].story_url,
+        "https://aeon.co/essays/ethical-values-can-be-both-objective-and-yet-without-a-foundation"
+    );
+    assert!(
+        results[0]
+            .image_url
+            .ends_with("?width=1200&quality=75&format=auto")
+    );
+    assert!(
+        results
+            .iter()
+            .all(|photo| !photo.story_url.contains("utm_"))
+    );
+
+    let canonical = r#"<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom" xmlns:media="http://search.yahoo.com/mrss/"><channel>
+        <title>t</title><link>https://example.com</link><description>d</description>
+        <item>
+          <title>Atom canonical</title>
+          <link>https://example.com/amp/one?partner=rss</link>
+          <atom:link href="https://Example.com/one/" rel="canonical" />
+          <atom:link href="https://example.com/one?smid=tw" rel="standout" />
+          <media:content url="https://example.com/one.jpg?utm_medium=rss" />
+        </item>
+        <item>
+          <title>Html canonical</title>
+          <link>https://example.com/two?utm_source=rss</link>
+          <description><![CDATA[<link rel="canonical" href="https://example.com/stories/two"><img src="https://example.com/two.jpg" alt="" />]]></description>
+        </item>
+      </channel></rss>"#;
+    let (results, _) = get_photos(Channel::from_str(canonical).unwrap());
+    assert_eq!(results[0].story_url, "https://example.com/one");
+    assert_eq!(
+        results[0].provenance.story_url,
+        Some("atom:link[0] canonical".to_string())
+    );
+    assert_eq!(results[0].image_url, "https://example.com/one.jpg");
+    assert_eq!(results[1].story_url, "https://example.com/stories/two");
+}
+
+#[test]
+fn parser_reads_refresh_hints_from_the_channel() {
+    /*
+    The BBC feed sets a <ttl> of 15 minutes, while the Japan Times uses the
+    syndication module to say it updates once an hour.
+     */
+
+    let feed = load_fixture("bbc.xml".to_string());
+    let (_, report) = get_photos(Channel::from_str(&feed.unwrap()).unwrap());
+    assert_eq!(report.refresh.ttl, Some(900));
+    assert_eq!(report.refresh.interval(), Some(900));
+
+    let feed = load_fixture("japantimes.xml".to_string());
+    let (_, report) = get_photos(Channel::from_str(&feed.unwrap()).unwrap());
+    assert_eq!(report.refresh.update_period, Some(3600));
+    assert_eq!(report.refresh.interval(), Some(3600));
+
+    let feed = load_fixture("nytimes.xml".to_string());
+    let (_, report) = get_photos(Channel::from_str(&feed.unwrap()).unwrap());
+    assert!(report.refresh.is_empty());
+    assert_eq!(report.refresh.interval(), None);
+
+    let skipping = r#"<rss version="2.0"><channel>
+        <title>t</title><link>https://example.com</link><description>d</description>
+        <ttl>60</ttl>
+        <skipHours><hour>0</hour><hour>1</hour><hour>24</hour></skipHours>
+        <skipDays><day>Sunday</day></skipDays>
+      </channel></rss>"#;
+    let (_, report) = get_photos(Channel::from_str(skipping).unwrap());
+    assert_eq!(report.refresh.skip_hours, vec![0, 1, 0]);
+    assert_eq!(report.refresh.skip_days, vec!["sunday".to_string()]);
+
+    // Friday 24 Nov 2023, 00:30 gmt is pushed back to 02:00
+    assert_eq!(report.refresh.skip(1700785800), 1700791200);
+    // Sunday 26 Nov 2023, 10:00 gmt is pushed past the rest of Sunday, and Monday's early hours
+    assert_eq!(report.refresh.skip(1700992800), 1701050400);
+    // Friday 24 Nov 2023, 12:00 gmt is fine as it is
+    assert_eq!(report.refresh.skip(1700827200), 1700827200);
+}
+
+#[test]
+fn parser_ignores_a_ttl_too_large_to_be_meant() {
+    let oversized = r#"<rss version="2.0"><channel>
+        <title>t</title><link>https://example.com</link><description>d</description>
+        <ttl>1000000000000000000</ttl>
+      </channel></rss>"#;
+    let (_, report) = get_photos(Channel::from_str(oversized).unwrap());
+    assert_eq!(report.refresh.ttl, None);
+    assert_eq!(report.refresh.interval(), None);
+}
+
+#[test]
+fn parser_reads_publication_dates() {
+    let channel = Channel::from_str(&load_fixture("aeon.xml".to_string()).unwrap()).unwrap();
+    let (results, report) = get_photos(channel);
+    // Fri, 24 Nov 2023 11:00:00 GMT
+    assert_eq!(results[0].published, Some(1700823600));
+    assert!(results.iter().all(|photo| photo.published.is_some()));
+    assert!(report.warnings.is_empty());
+
+    let mut item = Item::default();
+    item.set_pub_date("yesterday".to_string());
+    assert!(published(&item).is_err());
+    item.set_pub_date("Fri, 24 Nov 2023 06:00:00 -0500".to_string());
+    assert_eq!(published(&item), Ok(Some(1700823600)));
+    item.set_pub_date(None);
+    assert_eq!(published(&item), Ok(None));
+}
@@ -0,0 +1,160 @@
+use crate::http::HttpClient;
+use crate::parser::NewsPhoto;
+use image::imageops::{self, FilterType};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+/// Settings for spotting the same photo served under different urls, crops and resolutions.
+#[derive(Debug, Clone)]
+pub struct PhashConfig {
+    pub enabled: bool,
+    // most bits in which the hashes of two images can differ, for them to count as the same photo
+    pub max_distance: u32,
+}
+
+impl Default for PhashConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_distance: 6,
+        }
+    }
+}
+
+/// The difference hash of an image: shrunk to 9x8 in grayscale, each bit says whether
+/// a pixel is darker than the one to its right, which survives resizing, recompression
+/// and light cropping, but not a different picture.
+pub fn dhash(bytes: &[u8]) -> Result<u64, image::ImageError> {
+    let gray = image::load_from_memory(bytes)?.to_luma8();
+    let small = imageops::resize(&gray, 9, 8, FilterType::Triangle);
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash <<= 1;
+            if small.get_pixel(x, y)[0] < small.get_pixel(x + 1, y)[0] {
+                hash |= 1;
+            }
+        }
+    }
+    Ok(hash)
+}
+
+/// The number of bits in which two hashes differ.
+pub fn distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// The hash of every image downloaded so far, by url (or `None` if it could not be hashed),
+/// so each one is only ever downloaded once.
+#[derive(Debug, Clone)]
+pub struct ImageHashes {
+    hashes: Arc<Mutex<HashMap<String, Option<u64>>>>,
+    max_distance: u32,
+}
+
+impl ImageHashes {
+    pub fn new(max_distance: u32) -> Self {
+        Self {
+            hashes: Arc::default(),
+            max_distance,
+        }
+    }
+
+    pub fn max_distance(&self) -> u32 {
+        self.max_distance
+    }
+
+    pub fn get(&self, image_url: &str) -> Option<u64> {
+        self.hashes
+            .lock()
+            .ok()
+            .and_then(|hashes| hashes.get(image_url).copied().flatten())
+    }
+
+    pub fn insert(&self, image_url: &str, hash: Option<u64>) {
+        if let Ok(mut hashes) = self.hashes.lock() {
+            hashes.insert(image_url.to_string(), hash);
+        }
+    }
+
+    /// Forgets the images for which `keep` does not hold, i.e. those no longer in any feed.
+    pub fn retain(&self, keep: impl Fn(&str) -> bool) {
+        if let Ok(mut hashes) = self.hashes.lock() {
+            hashes.retain(|image_url, _| keep(image_url));
+        }
+    }
+
+    fn known(&self, image_url: &str) -> bool {
+        self.hashes
+            .lock()
+            .is_ok_and(|hashes| hashes.contains_key(image_url))
+    }
+
+    /// Downloads and hashes the image of every photo not seen before, all at once but
+    /// for as many at a time as `slots` allow, returning how many were.
+    pub async fn hash_images(
+        &self,
+        client: &HttpClient,
+        photos: &[NewsPhoto],
+        slots: &Arc<Semaphore>,
+    ) -> usize {
+        let mut queued = HashSet::new();
+        let mut downloads = JoinSet::new();
+        for photo in photos {
+            let image_url = photo.image_url.clone();
+            if image_url.is_empty() || self.known(&image_url) || !queued.insert(image_url.clone()) {
+                continue;
+            }
+            let (client, slots) = (client.clone(), slots.clone());
+            downloads.spawn(async move {
+                let hash = match slots.acquire_owned().await {
+                    Ok(_slot) => hash_image(&client, &image_url).await,
+                    Err(_) => None,
+                };
+                (image_url, hash)
+            });
+        }
+        let mut hashed = 0;
+        while let Some(downloaded) = downloads.join_next().await {
+            // failures are remembered too, rather than retried with every fetch of the feed,
+            // unless trying again later might succeed
+            if let Ok((image_url, Some(hash))) = downloaded {
+                self.insert(&image_url, hash);
+                hashed += 1;
+            }
+        }
+        hashed
+    }
+}
+
+/// The hash of the image at `image_url` (`None` inside if it cannot be had at all),
+/// or `None` if it could not be downloaded this time.
+async fn hash_image(client: &HttpClient, image_url: &str) -> Option<Option<u64>> {
+    match client.get(image_url).await {
+        Ok(bytes) => match tokio::task::spawn_blocking(move || dhash(&bytes)).await {
+            Ok(Ok(hash)) => Some(Some(hash)),
+            Ok(Err(err)) => {
+                log::debug!("image hash: cannot decode '{image_url}': {err}");
+                Some(None)
+            }
+            Err(err) => {
+                log::error!("image hash: '{image_url}': {err}");
+                None
+            }
+        },
+        Err(err) if err.is_transient() => {
+            log::debug!("image hash: cannot download '{image_url}' for now: {err}");
+            None
+        }
+        Err(err) => {
+            log::debug!("image hash: cannot download '{image_url}': {err}");
+            Some(None)
+        }
+    }
+}
+
+#[path = "phash_test.rs"]
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,97 @@
+use super::*;
+use crate::cache::CacheConfig;
+use crate::http::HttpConfig;
+use crate::test_server::{reply, response, serve};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+fn fixture(name: &str) -> Vec<u8> {
+    std::fs::read(format!("tests/fixtures/images/{name}")).unwrap()
+}
+
+fn hash_of(name: &str) -> u64 {
+    dhash(&fixture(name)).unwrap()
+}
+
+#[test]
+fn the_same_photo_hashes_alike_across_sizes_and_crops() {
+    let wire = hash_of("wire.jpg");
+    let max_distance = PhashConfig::default().max_distance;
+    // resized to half and recompressed, or cropped and recompressed
+    assert!(distance(wire, hash_of("wire-small.jpg")) <= max_distance);
+    assert!(distance(wire, hash_of("wire-cropped.jpg")) <= max_distance);
+    // another picture altogether
+    assert!(distance(wire, hash_of("other.jpg")) > max_distance);
+    assert_eq!(distance(wire, wire), 0);
+}
+
+#[test]
+fn anything_but_an_image_cannot_be_hashed() {
+    assert!(dhash(b"<html><body>Not Found</body></html>").is_err());
+}
+
+// serves the image fixtures (a 503 for busy.jpg, a 404 for anything else), counting
+// the requests
+async fn serve_images(requests: Arc<AtomicUsize>) -> String {
+    let address = serve(move |request, socket| {
+        requests.fetch_add(1, Ordering::SeqCst);
+        async move {
+            let name = request.path.trim_start_matches('/');
+            let response = match std::fs::read(format!("tests/fixtures/images/{name}")) {
+                _ if name == "busy.jpg" => response("503 Service Unavailable", ""),
+                Ok(body) => response("200 OK", body),
+                Err(_) => response("404 Not Found", ""),
+            };
+            reply(socket, response).await;
+        }
+    })
+    .await;
+    format!("http://{address}")
+}
+
+#[tokio::test]
+async fn images_are_only_downloaded_once() {
+    let requests = Arc::new(AtomicUsize::new(0));
+    let host = serve_images(requests.clone()).await;
+    let client = HttpClient::new(&HttpConfig {
+        cache: CacheConfig {
+            enabled: false,
+            ..CacheConfig::default()
+        },
+        ..HttpConfig::default()
+    })
+    .unwrap();
+    let photos: Vec<NewsPhoto> = [
+        "wire.jpg",
+        "other.jpg",
+        "missing.jpg",
+        "busy.jpg",
+        "wire.jpg",
+    ]
+    .iter()
+    .map(|name| NewsPhoto {
+        image_url: format!("{host}/{name}"),
+        story_url: format!("{host}/story/{name}"),
+        ..NewsPhoto::new()
+    })
+    .collect();
+
+    let slots = Arc::new(Semaphore::new(2));
+    let hashes = ImageHashes::new(6);
+    assert_eq!(hashes.hash_images(&client, &photos, &slots).await, 3);
+    assert_eq!(
+        hashes.get(&format!("{host}/wire.jpg")),
+        Some(hash_of("wire.jpg"))
+    );
+    assert_eq!(hashes.get(&format!("{host}/missing.jpg")), None);
+    assert!(!hashes.known(&format!("{host}/busy.jpg")));
+    assert_eq!(requests.load(Ordering::SeqCst), 4);
+
+    // neither the images hashed nor the one missing are downloaded again, only the one
+    // which might be had later
+    assert_eq!(hashes.hash_images(&client, &photos, &slots).await, 0);
+    assert_eq!(requests.load(Ordering::SeqCst), 5);
+
+    hashes.retain(|image_url| image_url.ends_with("other.jpg"));
+    assert_eq!(hashes.get(&format!("{host}/wire.jpg")), None);
+    assert_eq!(hashes.hash_images(&client, &photos[..1], &slots).await, 1);
+}
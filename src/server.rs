@@ -1,5 +1,8 @@
-use crate::loader::FeedDb;
-use crate::shuffler::randomize;
+use crate::feeds::Feed;
+use crate::index::SharedIndex;
+use crate::loader::{StatusDb, now};
+use crate::opml::to_opml;
+use crate::parser::MediaKind;
 use actix_files::Files;
 use actix_web::http::header::ContentType;
 use actix_web::middleware::Logger;
@@ -7,31 +10,40 @@ use actix_web::{App, HttpServer, dev::Server, web};
 use actix_web::{HttpRequest, HttpResponse};
 use serde::{Deserialize, Serialize};
 use std::net::TcpListener;
+use tokio::sync::watch;
 
 pub struct AppState {
-    pub feeds: FeedDb,
+    // the photos being served, published by the loader
+    pub index: SharedIndex,
+    pub status: StatusDb,
     pub next_size: usize,
+    // the list of feeds currently being fetched
+    pub catalog: watch::Receiver<Vec<Feed>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct NextOptions {
+    // include the provenance of each field in the results
+    #[serde(default)]
+    debug: bool,
+    // restrict the results to either photos or video poster frames
+    media: Option<MediaKind>,
 }
 
 async fn get_next(
     req: HttpRequest,
     offset: web::Path<String>,
+    options: web::Query<NextOptions>,
     state: web::Data<AppState>,
 ) -> HttpResponse {
-    let start: usize = match offset.to_string().parse() {
-        Ok(i) => i,
-        Err(_) => 0,
-    };
+    let start: usize = offset.to_string().parse().unwrap_or_default();
     let stop = &state.clone().next_size;
     let seed: u64 = match req.peer_addr() {
         Some(address) => address
             .ip()
             .to_string()
             .chars()
-            .into_iter()
-            .map(|c| c.to_digit(10))
-            .filter(|c| c.is_some())
-            .map(|c| c.unwrap())
+            .filter_map(|c| c.to_digit(10))
             .fold(1, |mut sum, x| {
                 sum += x;
                 sum
@@ -39,29 +51,22 @@ async fn get_next(
         None => 127,
     };
 
-    let mut body = String::new();
-    let feeds = &state.clone().feeds;
-    if let Ok(db) = feeds.lock() {
-        let photos = db.values().flatten().collect::<Vec<_>>();
-        let total = photos.len();
-        if start < total {
-            let mut subset = Vec::new();
-            let shuffle = randomize(seed, total);
-            for i in &shuffle[start..std::cmp::min(start + stop, total)] {
-                match photos.get(*i) {
-                    Some(photo) => subset.push(photo),
-                    None => log::error!("missing photo at index {i}"),
-                }
-            }
-            body = subset
-                .iter()
-                .map(|photo| photo.as_json())
-                .filter(|p| p.is_ok())
-                .map(|p| p.unwrap())
-                .collect::<Vec<_>>()
-                .join(",");
-        }
-    }
+    let at = now();
+    let index = state.index.load();
+    let subset = index.page(seed, start, *stop, |photo| {
+        options.media.is_none_or(|media| photo.media == media)
+            // photos can age past their expiry between fetches of their feed
+            && !photo.expired(at)
+    });
+    let body = subset
+        .iter()
+        .map(|photo| match options.debug {
+            true => photo.as_debug_json(),
+            false => photo.as_json(),
+        })
+        .filter_map(|p| p.ok())
+        .collect::<Vec<_>>()
+        .join(",");
 
     HttpResponse::Ok()
         .content_type(ContentType::json())
@@ -75,16 +80,7 @@ struct Status {
 }
 
 async fn health(state: web::Data<AppState>) -> HttpResponse {
-    let mut feed_count: usize = 0;
-    let mut photo_count: usize = 0;
-    let feeds = &state.clone().feeds;
-    if let Ok(db) = feeds.lock() {
-        feed_count = db.keys().len();
-        photo_count = db.values().flatten().fold(0, |mut count, _| {
-            count += 1;
-            count
-        });
-    }
+    let (feed_count, photo_count) = state.index.load().counts();
     let status = Status {
         feeds: feed_count,
         photos: photo_count,
@@ -99,34 +95,60 @@ async fn health(state: web::Data<AppState>) -> HttpResponse {
         .body(result)
 }
 
+async fn feed_status(state: web::Data<AppState>) -> HttpResponse {
+    let mut result = String::from("{}");
+    if let Ok(status) = state.status.lock() {
+        result = match serde_json::to_string(&*status) {
+            Ok(s) => s,
+            Err(_) => format!("{:#?}", status),
+        };
+    }
+
+    HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .body(result)
+}
+
+async fn feed_opml(state: web::Data<AppState>) -> HttpResponse {
+    let feeds = state.catalog.borrow().clone();
+    match to_opml(&feeds) {
+        Ok(opml) => HttpResponse::Ok()
+            .content_type("text/x-opml; charset=utf-8")
+            .body(opml),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
 pub fn run(
     listener: TcpListener,
-    db: FeedDb,
+    index: SharedIndex,
+    status: StatusDb,
+    catalog: watch::Receiver<Vec<Feed>>,
     next_size: usize,
     static_path: String,
 ) -> Result<Server, std::io::Error> {
     let server = HttpServer::new(move || {
         App::new()
             .app_data(web::Data::new(AppState {
-                feeds: db.clone(),
+                index: index.clone(),
+                status: status.clone(),
                 next_size,
+                catalog: catalog.clone(),
             }))
             .wrap(Logger::default())
             .wrap(Logger::new("%a %{User-Agent}i"))
             .route("/health", web::get().to(health))
             .service(
                 web::scope("/api")
-                    .service(web::resource("/next/{offset}").route(web::get().to(get_next))),
-            )
-            .service(
-                Files::new("/js", String::from(format!("{static_path}/static/js")))
-                    .index_file("loader.js"),
-            )
-            .service(
-                Files::new("/", String::from(format!("{static_path}/static/")))
-                    .index_file("index.html"),
+                    .service(web::resource("/next/{offset}").route(web::get().to(get_next)))
+                    .service(web::resource("/feeds").route(web::get().to(feed_status)))
+                    .service(web::resource("/feeds.opml").route(web::get().to(feed_opml))),
             )
+            .service(Files::new("/js", format!("{static_path}/static/js")).index_file("loader.js"))
+            .service(Files::new("/", format!("{static_path}/static/")).index_file("index.html"))
     })
+    // shutting down is up to the caller, which has the loader to stop first
+    .disable_signals()
     .listen(listener)?
     .run();
 
@@ -1,10 +1,16 @@
 use super::*;
-use crate::parser::NewsPhoto;
+use crate::error::FeedError;
+use crate::feeds::Feed;
+use crate::http::Validators;
+use crate::loader::{Breaker, FeedStatus};
+use crate::parser::{ExtractionReport, MediaKind, NewsPhoto, Provenance, Rejection};
+use crate::store::MemoryStore;
 use actix_web::{App, body::to_bytes, test, web};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
 
-fn set_app_state() -> AppState {
+fn parsed_feeds() -> HashMap<String, Vec<NewsPhoto>> {
     let mut parsed_feeds = HashMap::<String, Vec<NewsPhoto>>::new();
 
     parsed_feeds.insert(
@@ -14,13 +20,15 @@ fn set_app_state() -> AppState {
                 image_url: "https://static01.nyt.com/images/2023/11/23/multimedia/23finland-border-kmbp/23finland-border-kmbp-mediumSquareAt3X.jpg".to_string(), 
                 story_url: "https://www.nytimes.com/2023/11/23/world/europe/finland-russia-border-migrants.html".to_string(), 
                 description: Some("Finnish border guards escorting migrants at the international crossing with Russia near Salla, Finland, on Thursday.".to_string()),
-                credit: Some("Jussi Nukari/Lehtikuva, via Associated Press".to_string())
+                credit: Some("Jussi Nukari/Lehtikuva, via Associated Press".to_string()),
+                ..NewsPhoto::new()
             },
             NewsPhoto {
                 image_url: "https://static01.nyt.com/images/2023/11/23/multimedia/23themorning-lead-promo/23themorning-lead-bmhq-mediumSquareAt3X.jpg".to_string(),
                 story_url: "https://www.nytimes.com/2023/11/23/briefing/thanksgiving-pep-talk.html".to_string(),
                 description: Some("A Thanksgiving Pep Talk".to_string()),
-                credit: Some("Johnny Miller for The New York Times".to_string())
+                credit: Some("Johnny Miller for The New York Times".to_string()),
+                ..NewsPhoto::new()
             },
         ],
     );
@@ -31,15 +39,67 @@ fn set_app_state() -> AppState {
                 image_url: "https://s.france24.com/media/display/98336912-8a11-11ee-9a7e-005056bf30b7/w:1024/p:16x9/ENBT%20BIL%20SILICON%20VALLEY%20PUSH%20PICTURE.jpg".to_string(),
                 story_url: "https://www.france24.com/en/tv-shows/revisited/20231124-bouncing-back-silicon-valley-bets-on-ai-to-regain-past-glory".to_string(),
                 description: Some("Bouncing back: Silicon Valley bets on AI to regain past glory".to_string()),
-                credit: Some("Pierrick LEURENT".to_string())
+                credit: Some("Pierrick LEURENT".to_string()),
+                provenance: Provenance {
+                    image_url: Some("media:content[0]".to_string()),
+                    story_url: Some("link".to_string()),
+                    description: Some("title".to_string()),
+                    credit: Some("media:credit[0]".to_string()),
+                    ..Provenance::default()
+                },
+                ..NewsPhoto::new()
             },
         ],
     );
 
-    let feed_db = Arc::new(Mutex::new(parsed_feeds));
+    parsed_feeds
+}
+
+fn published(feeds: HashMap<String, Vec<NewsPhoto>>) -> SharedIndex {
+    let index = SharedIndex::default();
+    index.publish(&MemoryStore::from(feeds)).unwrap();
+    index
+}
+
+fn set_app_state() -> AppState {
+    let mut feed_status = HashMap::<String, FeedStatus>::new();
+    feed_status.insert(
+        "https://www.france24.com/en/rss".to_string(),
+        FeedStatus {
+            last_fetched: 1700841600,
+            report: ExtractionReport {
+                items: 2,
+                accepted: 1,
+                rejected: BTreeMap::from([(Rejection::NoImage, 1)]),
+                warnings: Vec::new(),
+                ..ExtractionReport::default()
+            },
+            last_error: Some(FeedError::Timeout),
+            errors: BTreeMap::from([("timeout".to_string(), 1)]),
+            interval: 3600,
+            next_fetch: 1700845200,
+            failures: 1,
+            breaker: Breaker::Closed,
+            validators: Validators {
+                etag: Some("\"5f3a\"".to_string()),
+                last_modified: None,
+            },
+            hits: 3,
+            misses: 1,
+            evicted: None,
+        },
+    );
+
     AppState {
-        feeds: feed_db,
+        index: published(parsed_feeds()),
+        status: Arc::new(Mutex::new(feed_status)),
         next_size: 3,
+        catalog: watch::channel(vec![Feed {
+            name: Some("France 24".to_string()),
+            section: Some("world".to_string()),
+            ..Feed::new("https://www.france24.com/en/rss")
+        }])
+        .1,
     }
 }
 
@@ -49,7 +109,9 @@ async fn endpoints_test() {
         App::new()
             .app_data(web::Data::new(set_app_state()))
             .service(web::resource("/health").route(web::get().to(health)))
-            .service(web::resource("/api/next/{offset}").route(web::get().to(get_next))),
+            .service(web::resource("/api/next/{offset}").route(web::get().to(get_next)))
+            .service(web::resource("/api/feeds").route(web::get().to(feed_status)))
+            .service(web::resource("/api/feeds.opml").route(web::get().to(feed_opml))),
     )
     .await;
 
@@ -62,17 +124,153 @@ async fn endpoints_test() {
         r###"{"feeds":2,"photos":3}"###
     );
 
-    // retrieve the three photos in shared memory
+    // retrieve the three photos in shared memory, each with the feed it appeared in
     let fetch_request = test::TestRequest::get().uri("/api/next/0").to_request();
     let fetch_response = test::call_service(&app, fetch_request).await;
     assert!(fetch_response.status().is_success());
     let fetch_response_body = to_bytes(fetch_response.into_body()).await.unwrap();
-    assert_eq!(fetch_response_body.len(), 1126);
+    assert_eq!(fetch_response_body.len(), 1310);
 
     // attempt to fetch beyond what is available: should result in an empty list
     let excess_fetch_request = test::TestRequest::get().uri("/api/next/4").to_request();
     let excess_fetch_response = test::call_service(&app, excess_fetch_request).await;
     assert!(excess_fetch_response.status().is_success());
     let excess_fetch_response_body = to_bytes(excess_fetch_response.into_body()).await.unwrap();
-    assert_eq!(excess_fetch_response_body.len(), 2)
+    assert_eq!(excess_fetch_response_body.len(), 2);
+
+    // the extraction report of each feed
+    let status_request = test::TestRequest::get().uri("/api/feeds").to_request();
+    let status_response = test::call_service(&app, status_request).await;
+    assert!(status_response.status().is_success());
+    let status_response_body = to_bytes(status_response.into_body()).await.unwrap();
+    assert_eq!(
+        status_response_body.to_owned(),
+        r###"{"https://www.france24.com/en/rss":{"last_fetched":1700841600,"report":{"items":2,"accepted":1,"rejected":{"no_image":1},"warnings":[]},"last_error":{"kind":"timeout"},"errors":{"timeout":1},"interval":3600,"next_fetch":1700845200,"failures":1,"breaker":"closed","validators":{"etag":"\"5f3a\""},"hits":3,"misses":1}}"###
+    );
+
+    // the feeds being fetched, for feed readers
+    let opml_request = test::TestRequest::get().uri("/api/feeds.opml").to_request();
+    let opml_response = test::call_service(&app, opml_request).await;
+    assert!(opml_response.status().is_success());
+    let opml_response_body = to_bytes(opml_response.into_body()).await.unwrap();
+    let opml = String::from_utf8_lossy(&opml_response_body);
+    assert!(opml.contains(r#"<outline text="world" title="world">"#));
+    assert!(opml.contains(
+        r#"<outline type="rss" text="France 24" title="France 24" xmlUrl="https://www.france24.com/en/rss"/>"#
+    ));
+}
+
+#[actix_web::test]
+async fn debug_flag_includes_provenance() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(set_app_state()))
+            .service(web::resource("/api/next/{offset}").route(web::get().to(get_next))),
+    )
+    .await;
+
+    // provenance is not part of the regular results
+    let fetch_request = test::TestRequest::get().uri("/api/next/0").to_request();
+    let fetch_response = test::call_service(&app, fetch_request).await;
+    let fetch_response_body = to_bytes(fetch_response.into_body()).await.unwrap();
+    let photos: Vec<serde_json::Value> = serde_json::from_slice(&fetch_response_body).unwrap();
+    assert!(photos.iter().all(|photo| photo.get("provenance").is_none()));
+
+    // but is included for every result when the debug flag is set
+    let debug_request = test::TestRequest::get()
+        .uri("/api/next/0?debug=true")
+        .to_request();
+    let debug_response = test::call_service(&app, debug_request).await;
+    assert!(debug_response.status().is_success());
+    let debug_response_body = to_bytes(debug_response.into_body()).await.unwrap();
+    let photos: Vec<serde_json::Value> = serde_json::from_slice(&debug_response_body).unwrap();
+    assert_eq!(photos.len(), 3);
+    assert!(photos.iter().all(|photo| photo.get("provenance").is_some()));
+
+    let france24 = photos
+        .iter()
+        .find(|photo| photo["story_url"].as_str().unwrap().contains("france24"))
+        .unwrap();
+    assert_eq!(france24["provenance"]["image_url"], "media:content[0]");
+    assert_eq!(france24["provenance"]["credit"], "media:credit[0]");
+}
+
+#[actix_web::test]
+async fn video_poster_frames_can_be_included_or_excluded() {
+    let mut state = set_app_state();
+    let mut feeds = parsed_feeds();
+    feeds.insert(
+        "https://www.france24.com/en/video/rss".to_string(),
+        vec![NewsPhoto {
+            image_url: "https://s.france24.com/media/display/poster.jpg".to_string(),
+            story_url: "https://www.france24.com/en/video/20231124-story".to_string(),
+            media: MediaKind::Video,
+            video_url: Some("https://s.france24.com/media/video.mp4".to_string()),
+            ..NewsPhoto::new()
+        }],
+    );
+    state.index = published(feeds);
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .service(web::resource("/api/next/{offset}").route(web::get().to(get_next))),
+    )
+    .await;
+
+    // the page size is 3, so two pages cover all four entries
+    for (query, expected_photos, expected_videos) in
+        [("?media=photo", 3, 0), ("?media=video", 0, 1), ("", 3, 1)]
+    {
+        let mut photos: Vec<NewsPhoto> = Vec::new();
+        for offset in [0, 3] {
+            let uri = format!("/api/next/{offset}{query}");
+            let request = test::TestRequest::get().uri(&uri).to_request();
+            let response = test::call_service(&app, request).await;
+            assert!(response.status().is_success());
+            let body = to_bytes(response.into_body()).await.unwrap();
+            let page: Vec<NewsPhoto> = serde_json::from_slice(&body).unwrap();
+            photos.extend(page);
+        }
+        let videos = photos.iter().filter(|p| p.media == MediaKind::Video);
+        assert_eq!(videos.count(), expected_videos, "{query}");
+        assert_eq!(
+            photos.iter().filter(|p| p.media.is_photo()).count(),
+            expected_photos,
+            "{query}"
+        );
+    }
+}
+
+#[actix_web::test]
+async fn expired_photos_are_never_served() {
+    let mut state = set_app_state();
+    let fresh = NewsPhoto {
+        image_url: "https://example.com/fresh.jpg".to_string(),
+        story_url: "https://example.com/fresh".to_string(),
+        expires_at: Some(u64::MAX),
+        ..NewsPhoto::new()
+    };
+    let expired = NewsPhoto {
+        image_url: "https://example.com/expired.jpg".to_string(),
+        story_url: "https://example.com/expired".to_string(),
+        expires_at: Some(1700841600),
+        ..NewsPhoto::new()
+    };
+    state.index = published(HashMap::from([(
+        "https://example.com/rss".to_string(),
+        vec![fresh, expired],
+    )]));
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .service(web::resource("/api/next/{offset}").route(web::get().to(get_next))),
+    )
+    .await;
+
+    let request = test::TestRequest::get().uri("/api/next/0").to_request();
+    let response = test::call_service(&app, request).await;
+    let body = to_bytes(response.into_body()).await.unwrap();
+    let photos: Vec<NewsPhoto> = serde_json::from_slice(&body).unwrap();
+    assert_eq!(photos.len(), 1);
+    assert_eq!(photos[0].story_url, "https://example.com/fresh");
 }
@@ -4,6 +4,7 @@ use rand::seq::SliceRandom;
 
 pub fn randomize(seed: u64, max_val: usize) -> Vec<usize> {
     let mut rng = StdRng::seed_from_u64(seed);
+    #[allow(clippy::useless_conversion, clippy::map_identity)]
     let mut x = (0..max_val).into_iter().map(|i| i).collect::<Vec<_>>();
     x.shuffle(&mut rng);
     x
@@ -0,0 +1,157 @@
+use crate::loader::{FeedDb, now};
+use crate::parser::{NewsPhoto, Provenance};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time::{self, Instant, MissedTickBehavior};
+
+// bump whenever the layout of a snapshot (or of NewsPhoto) changes incompatibly
+pub const VERSION: u32 = 1;
+
+/// The photos of every feed, as saved to disk.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    // seconds since the unix epoch
+    pub saved_at: u64,
+    #[serde(with = "with_provenance")]
+    pub feeds: HashMap<String, Vec<NewsPhoto>>,
+}
+
+/// A photo as saved, along with the provenance its json leaves out.
+#[derive(Serialize)]
+struct SavedPhoto<'a> {
+    #[serde(flatten)]
+    photo: &'a NewsPhoto,
+    provenance: &'a Provenance,
+}
+
+#[derive(Deserialize)]
+struct RestoredPhoto {
+    #[serde(flatten)]
+    photo: NewsPhoto,
+    // missing from snapshots saved before it was
+    #[serde(default)]
+    provenance: Provenance,
+}
+
+mod with_provenance {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        feeds: &HashMap<String, Vec<NewsPhoto>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(feeds.iter().map(|(url, photos)| {
+            let photos = photos
+                .iter()
+                .map(|photo| SavedPhoto {
+                    photo,
+                    provenance: &photo.provenance,
+                })
+                .collect::<Vec<_>>();
+            (url, photos)
+        }))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<HashMap<String, Vec<NewsPhoto>>, D::Error> {
+        let feeds = HashMap::<String, Vec<RestoredPhoto>>::deserialize(deserializer)?;
+        Ok(feeds
+            .into_iter()
+            .map(|(url, photos)| {
+                let photos = photos
+                    .into_iter()
+                    .map(|restored| NewsPhoto {
+                        provenance: restored.provenance,
+                        ..restored.photo
+                    })
+                    .collect();
+                (url, photos)
+            })
+            .collect())
+    }
+}
+
+#[derive(Deserialize)]
+struct Header {
+    version: u32,
+}
+
+/// Writes the FeedDb to `path` (through a temporary file, so a crash mid-write never
+/// leaves a truncated snapshot behind), returning the number of photos saved.
+pub fn save(db: &FeedDb, path: &Path) -> std::io::Result<usize> {
+    let feeds: HashMap<_, _> = db
+        .photos_by_feed()
+        .map_err(Error::other)?
+        .into_iter()
+        .collect();
+    let photos = feeds.values().map(Vec::len).sum();
+    let snapshot = Snapshot {
+        version: VERSION,
+        saved_at: now(),
+        feeds,
+    };
+    let json = serde_json::to_vec(&snapshot).map_err(Error::other)?;
+
+    let mut partial = PathBuf::from(path).into_os_string();
+    partial.push(".partial");
+    fs::write(&partial, json)?;
+    fs::rename(&partial, path)?;
+    Ok(photos)
+}
+
+/// Reads the photos of every feed back from a snapshot written by `save`.
+pub fn load(path: &Path) -> std::io::Result<Snapshot> {
+    let json = fs::read(path)?;
+    let header: Header =
+        serde_json::from_slice(&json).map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+    if header.version != VERSION {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "snapshot version {} is not supported (expected {VERSION})",
+                header.version
+            ),
+        ));
+    }
+    serde_json::from_slice(&json).map_err(|err| Error::new(ErrorKind::InvalidData, err))
+}
+
+/// Saves the FeedDb to `path` every `interval` seconds, until `shutdown` (after which any
+/// save under way is finished before returning, so it cannot race the last one).
+pub async fn background(
+    db: FeedDb,
+    path: PathBuf,
+    interval: u64,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    // the FeedDb was either just restored or is still empty, so there is nothing to save yet
+    let period = Duration::from_secs(interval.max(1));
+    let mut ticker = time::interval_at(Instant::now() + period, period);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = shutdown.wait_for(|stopping| *stopping) => break,
+        }
+        let db = db.clone();
+        let target = path.clone();
+        match tokio::task::spawn_blocking(move || save(&db, &target)).await {
+            Ok(Ok(photos)) => {
+                log::debug!("snapshot: saved {photos} photos to '{}'", path.display())
+            }
+            Ok(Err(err)) => log::warn!("snapshot: could not save '{}': {err}", path.display()),
+            Err(err) => log::warn!("snapshot: saving failed {err}"),
+        }
+    }
+}
+
+#[path = "snapshot_test.rs"]
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,89 @@
+use super::*;
+use crate::store::MemoryStore;
+use std::sync::Arc;
+
+fn snapshot_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "photojournalism-{name}-{}.json",
+        std::process::id()
+    ))
+}
+
+#[test]
+fn snapshots_restore_what_was_saved() {
+    let path = snapshot_path("snapshot-round-trip");
+    let photo = NewsPhoto {
+        image_url: "https://example.com/photo.jpg".to_string(),
+        story_url: "https://example.com/story".to_string(),
+        first_seen: 1700841600,
+        last_seen: 1700845200,
+        published: Some(1700838000),
+        provenance: Provenance {
+            image_url: Some("media:content[1]".to_string()),
+            story_url: Some("link".to_string()),
+            ignored: vec!["enclosure[0]".to_string()],
+            ..Provenance::default()
+        },
+        ..NewsPhoto::new()
+    };
+    let db: FeedDb = Arc::new(MemoryStore::from(HashMap::from([(
+        "https://example.com/rss".to_string(),
+        vec![photo.clone(), photo.clone()],
+    )])));
+
+    assert_eq!(save(&db, &path).unwrap(), 2);
+    let snapshot = load(&path).unwrap();
+    assert_eq!(snapshot.version, VERSION);
+    let restored = &snapshot.feeds["https://example.com/rss"];
+    assert_eq!(restored.len(), 2);
+    assert_eq!(restored[0].first_seen, 1700841600);
+    assert_eq!(restored[0].published, Some(1700838000));
+    assert_eq!(restored[0].provenance, photo.provenance);
+    let _ = fs::remove_file(&path);
+}
+
+#[test]
+fn snapshots_of_other_versions_are_refused() {
+    let path = snapshot_path("snapshot-version");
+    fs::write(&path, r#"{"version":999,"saved_at":0,"feeds":{}}"#).unwrap();
+    let err = load(&path).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    assert!(err.to_string().contains("version 999"));
+    let _ = fs::remove_file(&path);
+
+    assert_eq!(
+        load(&snapshot_path("snapshot-missing")).unwrap_err().kind(),
+        ErrorKind::NotFound
+    );
+}
+
+#[test]
+fn snapshots_saved_without_provenance_still_load() {
+    let path = snapshot_path("snapshot-without-provenance");
+    fs::write(
+        &path,
+        r#"{"version":1,"saved_at":0,"feeds":{"https://example.com/rss":[
+            {"image_url":"https://example.com/photo.jpg","story_url":"https://example.com/story",
+             "description":null,"credit":null}]}}"#,
+    )
+    .unwrap();
+    let snapshot = load(&path).unwrap();
+    let restored = &snapshot.feeds["https://example.com/rss"];
+    assert_eq!(restored[0].story_url, "https://example.com/story");
+    assert_eq!(restored[0].provenance, Provenance::default());
+    let _ = fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn periodic_snapshots_stop_at_shutdown() {
+    let path = snapshot_path("snapshot-periodic");
+    let db: FeedDb = Arc::new(MemoryStore::default());
+    let (stop, shutdown) = watch::channel(false);
+    let saving = tokio::spawn(background(db, path.clone(), 3600, shutdown));
+    stop.send(true).unwrap();
+    time::timeout(Duration::from_secs(5), saving)
+        .await
+        .expect("periodic snapshots did not stop")
+        .unwrap();
+    assert!(!path.exists());
+}
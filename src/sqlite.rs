@@ -0,0 +1,195 @@
+use crate::parser::{NewsPhoto, Provenance};
+use crate::store::{Store, StoreError, StoreResult};
+use rusqlite::{Connection, OptionalExtension, params};
+use std::path::Path;
+use std::sync::Mutex;
+
+// bump (and migrate in `open`) whenever the schema changes
+const SCHEMA_VERSION: i64 = 1;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS photos (
+    feed TEXT NOT NULL,
+    id TEXT NOT NULL,
+    position INTEGER NOT NULL,
+    first_seen INTEGER NOT NULL,
+    last_seen INTEGER NOT NULL,
+    published INTEGER,
+    photo TEXT NOT NULL,
+    provenance TEXT NOT NULL,
+    PRIMARY KEY (feed, id)
+);
+CREATE INDEX IF NOT EXISTS photos_by_id ON photos (id);
+CREATE INDEX IF NOT EXISTS photos_by_first_seen ON photos (first_seen);
+";
+
+impl From<rusqlite::Error> for StoreError {
+    fn from(err: rusqlite::Error) -> Self {
+        StoreError::Backend {
+            message: err.to_string(),
+        }
+    }
+}
+
+impl From<serde_json::Error> for StoreError {
+    fn from(err: serde_json::Error) -> Self {
+        StoreError::Backend {
+            message: err.to_string(),
+        }
+    }
+}
+
+/// Keeps the photos of every feed in an embedded SQLite database, one row per photo.
+pub struct SqliteStore {
+    connection: Mutex<Connection>,
+}
+
+// every photo is stored as its json (which leaves out the provenance, so that goes alongside)
+fn from_row(row: &rusqlite::Row) -> rusqlite::Result<(String, String)> {
+    from_row_at(row, 0)
+}
+
+fn from_row_at(row: &rusqlite::Row, column: usize) -> rusqlite::Result<(String, String)> {
+    Ok((row.get(column)?, row.get(column + 1)?))
+}
+
+fn to_photo((photo, provenance): (String, String)) -> StoreResult<NewsPhoto> {
+    let mut photo: NewsPhoto = serde_json::from_str(&photo)?;
+    photo.provenance = serde_json::from_str::<Provenance>(&provenance)?;
+    Ok(photo)
+}
+
+impl SqliteStore {
+    /// Opens (or creates) the database at `path`.
+    pub fn open(path: &Path) -> StoreResult<Self> {
+        Self::with_connection(Connection::open(path)?)
+    }
+
+    /// A database which lives only as long as the store, for tests.
+    pub fn in_memory() -> StoreResult<Self> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(connection: Connection) -> StoreResult<Self> {
+        let version: i64 = connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version > SCHEMA_VERSION {
+            return Err(StoreError::Backend {
+                message: format!(
+                    "database schema version {version} is newer than this build ({SCHEMA_VERSION})"
+                ),
+            });
+        }
+        connection.execute_batch(SCHEMA)?;
+        connection.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    fn select(&self, filter: &str, params: impl rusqlite::Params) -> StoreResult<Vec<NewsPhoto>> {
+        let connection = self.connection.lock()?;
+        let mut statement = connection.prepare(&format!(
+            "SELECT photo, provenance FROM photos {filter} ORDER BY feed, position"
+        ))?;
+        let rows = statement.query_map(params, from_row)?;
+        rows.map(|row| to_photo(row?)).collect()
+    }
+}
+
+impl Store for SqliteStore {
+    fn upsert_feed(&self, feed: &str, photos: Vec<NewsPhoto>) -> StoreResult<()> {
+        let mut connection = self.connection.lock()?;
+        let transaction = connection.transaction()?;
+        transaction.execute("DELETE FROM photos WHERE feed = ?1", params![feed])?;
+        {
+            // the primary key leaves all but the first of any photos sharing an identity out
+            let mut insert = transaction.prepare(
+                "INSERT OR IGNORE INTO photos
+                 (feed, id, position, first_seen, last_seen, published, photo, provenance)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            )?;
+            for (position, photo) in photos.iter().enumerate() {
+                insert.execute(params![
+                    feed,
+                    photo.identity(),
+                    position as i64,
+                    photo.first_seen as i64,
+                    photo.last_seen as i64,
+                    photo.published.map(|published| published as i64),
+                    serde_json::to_string(photo)?,
+                    serde_json::to_string(&photo.provenance)?,
+                ])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn remove_feed(&self, feed: &str) -> StoreResult<usize> {
+        let connection = self.connection.lock()?;
+        Ok(connection.execute("DELETE FROM photos WHERE feed = ?1", params![feed])?)
+    }
+
+    fn feed_photos(&self, feed: &str) -> StoreResult<Vec<NewsPhoto>> {
+        self.select("WHERE feed = ?1", params![feed])
+    }
+
+    fn feeds(&self) -> StoreResult<Vec<String>> {
+        let connection = self.connection.lock()?;
+        let mut statement = connection.prepare("SELECT DISTINCT feed FROM photos ORDER BY feed")?;
+        let feeds = statement.query_map([], |row| row.get(0))?;
+        Ok(feeds.collect::<Result<_, _>>()?)
+    }
+
+    fn list(&self) -> StoreResult<Vec<NewsPhoto>> {
+        self.select("", [])
+    }
+
+    fn photos_by_feed(&self) -> StoreResult<Vec<(String, Vec<NewsPhoto>)>> {
+        let connection = self.connection.lock()?;
+        let mut statement = connection
+            .prepare("SELECT feed, photo, provenance FROM photos ORDER BY feed, position")?;
+        let rows = statement.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, from_row_at(row, 1)?))
+        })?;
+        let mut by_feed: Vec<(String, Vec<NewsPhoto>)> = Vec::new();
+        for row in rows {
+            let (feed, photo) = row?;
+            let photo = to_photo(photo)?;
+            match by_feed.last_mut() {
+                Some((last, photos)) if *last == feed => photos.push(photo),
+                _ => by_feed.push((feed, vec![photo])),
+            }
+        }
+        Ok(by_feed)
+    }
+
+    fn counts(&self) -> StoreResult<(usize, usize)> {
+        let connection = self.connection.lock()?;
+        let (feeds, photos): (i64, i64) = connection.query_row(
+            "SELECT COUNT(DISTINCT feed), COUNT(*) FROM photos",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        Ok((feeds as usize, photos as usize))
+    }
+
+    fn get(&self, id: &str) -> StoreResult<Option<NewsPhoto>> {
+        let connection = self.connection.lock()?;
+        let row = connection
+            .query_row(
+                "SELECT photo, provenance FROM photos WHERE id = ?1 ORDER BY feed LIMIT 1",
+                params![id],
+                from_row,
+            )
+            .optional()?;
+        row.map(to_photo).transpose()
+    }
+
+    fn first_seen_between(&self, from: u64, to: u64) -> StoreResult<Vec<NewsPhoto>> {
+        self.select(
+            "WHERE first_seen >= ?1 AND first_seen < ?2",
+            params![from as i64, to as i64],
+        )
+    }
+}
@@ -0,0 +1,163 @@
+use crate::parser::NewsPhoto;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::Mutex;
+
+/// Why a `Store` could not do what was asked of it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StoreError {
+    // a thread panicked while holding the lock on the store
+    Poisoned,
+    Backend { message: String },
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::Poisoned => write!(f, "store lock poisoned"),
+            StoreError::Backend { message } => write!(f, "store backend error: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl<T> From<std::sync::PoisonError<T>> for StoreError {
+    fn from(_: std::sync::PoisonError<T>) -> Self {
+        StoreError::Poisoned
+    }
+}
+
+pub type StoreResult<T> = Result<T, StoreError>;
+
+/// Where the photos of every feed are kept, shared by the loader and the server.
+///
+/// Photos are identified by `NewsPhoto::identity()`, and listed in a stable order
+/// (by feed, then as the feed had them), so that shuffled pages stay consistent.
+pub trait Store: Send + Sync {
+    /// Replaces the photos of `feed` with `photos`, keeping only the first of any
+    /// which share an identity.
+    fn upsert_feed(&self, feed: &str, photos: Vec<NewsPhoto>) -> StoreResult<()>;
+
+    /// Drops the photos of `feed`, returning how many there were.
+    fn remove_feed(&self, feed: &str) -> StoreResult<usize>;
+
+    fn feed_photos(&self, feed: &str) -> StoreResult<Vec<NewsPhoto>>;
+
+    /// The urls of every feed with photos in the store.
+    fn feeds(&self) -> StoreResult<Vec<String>>;
+
+    fn list(&self) -> StoreResult<Vec<NewsPhoto>>;
+
+    /// Every photo, by feed (in the order of `feeds`), all read at once.
+    fn photos_by_feed(&self) -> StoreResult<Vec<(String, Vec<NewsPhoto>)>>;
+
+    /// The number of feeds, and of photos.
+    fn counts(&self) -> StoreResult<(usize, usize)>;
+
+    fn get(&self, id: &str) -> StoreResult<Option<NewsPhoto>>;
+
+    /// The photos first seen at or after `from`, and before `to` (seconds since the unix epoch).
+    fn first_seen_between(&self, from: u64, to: u64) -> StoreResult<Vec<NewsPhoto>>;
+}
+
+/// Keeps everything in a `HashMap` in memory, as the service always has.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    feeds: Mutex<HashMap<String, Vec<NewsPhoto>>>,
+}
+
+impl From<HashMap<String, Vec<NewsPhoto>>> for MemoryStore {
+    fn from(feeds: HashMap<String, Vec<NewsPhoto>>) -> Self {
+        Self {
+            feeds: Mutex::new(feeds),
+        }
+    }
+}
+
+impl MemoryStore {
+    // every photo, by feed in a stable order
+    fn sorted(feeds: &HashMap<String, Vec<NewsPhoto>>) -> Vec<(&String, &Vec<NewsPhoto>)> {
+        let mut sorted: Vec<_> = feeds.iter().collect();
+        sorted.sort_by(|a, b| a.0.cmp(b.0));
+        sorted
+    }
+}
+
+impl Store for MemoryStore {
+    fn upsert_feed(&self, feed: &str, mut photos: Vec<NewsPhoto>) -> StoreResult<()> {
+        let mut ids = HashSet::new();
+        photos.retain(|photo| ids.insert(photo.identity().to_string()));
+        let mut feeds = self.feeds.lock()?;
+        // a feed without photos is not kept at all, as in any other store
+        if photos.is_empty() {
+            feeds.remove(feed);
+        } else {
+            feeds.insert(feed.to_string(), photos);
+        }
+        Ok(())
+    }
+
+    fn remove_feed(&self, feed: &str) -> StoreResult<usize> {
+        Ok(self
+            .feeds
+            .lock()?
+            .remove(feed)
+            .map(|photos| photos.len())
+            .unwrap_or_default())
+    }
+
+    fn feed_photos(&self, feed: &str) -> StoreResult<Vec<NewsPhoto>> {
+        Ok(self.feeds.lock()?.get(feed).cloned().unwrap_or_default())
+    }
+
+    fn feeds(&self) -> StoreResult<Vec<String>> {
+        let feeds = self.feeds.lock()?;
+        Ok(Self::sorted(&feeds)
+            .into_iter()
+            .map(|(url, _)| url.clone())
+            .collect())
+    }
+
+    fn list(&self) -> StoreResult<Vec<NewsPhoto>> {
+        let feeds = self.feeds.lock()?;
+        Ok(Self::sorted(&feeds)
+            .into_iter()
+            .flat_map(|(_, photos)| photos.iter().cloned())
+            .collect())
+    }
+
+    fn photos_by_feed(&self) -> StoreResult<Vec<(String, Vec<NewsPhoto>)>> {
+        let feeds = self.feeds.lock()?;
+        Ok(Self::sorted(&feeds)
+            .into_iter()
+            .map(|(url, photos)| (url.clone(), photos.clone()))
+            .collect())
+    }
+
+    fn counts(&self) -> StoreResult<(usize, usize)> {
+        let feeds = self.feeds.lock()?;
+        Ok((feeds.len(), feeds.values().map(Vec::len).sum()))
+    }
+
+    fn get(&self, id: &str) -> StoreResult<Option<NewsPhoto>> {
+        let feeds = self.feeds.lock()?;
+        Ok(Self::sorted(&feeds)
+            .into_iter()
+            .flat_map(|(_, photos)| photos.iter())
+            .find(|photo| photo.identity() == id)
+            .cloned())
+    }
+
+    fn first_seen_between(&self, from: u64, to: u64) -> StoreResult<Vec<NewsPhoto>> {
+        Ok(self
+            .list()?
+            .into_iter()
+            .filter(|photo| (from..to).contains(&photo.first_seen))
+            .collect())
+    }
+}
+
+#[path = "store_test.rs"]
+#[cfg(test)]
+mod tests;
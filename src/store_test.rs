@@ -0,0 +1,157 @@
+use super::*;
+use crate::sqlite::SqliteStore;
+
+fn photo(story: &str, first_seen: u64) -> NewsPhoto {
+    NewsPhoto {
+        image_url: format!("{story}.jpg"),
+        story_url: story.to_string(),
+        first_seen,
+        last_seen: first_seen,
+        ..NewsPhoto::new()
+    }
+}
+
+// every backend has to behave the same way, so each one goes through the same checks
+fn check_store(store: &dyn Store) {
+    let aeon = "https://aeon.co/feed.rss";
+    let france24 = "https://www.france24.com/en/rss";
+    assert_eq!(store.counts().unwrap(), (0, 0));
+    assert!(store.list().unwrap().is_empty());
+
+    let mut kept = photo("https://aeon.co/essays/kept", 1700841600);
+    kept.provenance.story_url = Some("link".to_string());
+    store
+        .upsert_feed(
+            aeon,
+            vec![
+                kept.clone(),
+                photo("https://aeon.co/essays/gone", 1700845200),
+            ],
+        )
+        .unwrap();
+    store
+        .upsert_feed(
+            france24,
+            vec![photo("https://www.france24.com/en/world", 1700848800)],
+        )
+        .unwrap();
+    assert_eq!(store.counts().unwrap(), (2, 3));
+    assert_eq!(store.feeds().unwrap(), vec![aeon, france24]);
+
+    // upserting a feed replaces its photos, in the order given
+    store
+        .upsert_feed(
+            aeon,
+            vec![
+                photo("https://aeon.co/essays/new", 1700852400),
+                kept.clone(),
+            ],
+        )
+        .unwrap();
+    let stories: Vec<_> = store
+        .list()
+        .unwrap()
+        .into_iter()
+        .map(|p| p.story_url)
+        .collect();
+    assert_eq!(
+        stories,
+        vec![
+            "https://aeon.co/essays/new",
+            "https://aeon.co/essays/kept",
+            "https://www.france24.com/en/world"
+        ]
+    );
+    assert_eq!(store.feed_photos(aeon).unwrap().len(), 2);
+
+    // a story listed twice is kept once, as first listed
+    let relisted = NewsPhoto {
+        image_url: "https://www.france24.com/en/world-2.jpg".to_string(),
+        ..photo("https://www.france24.com/en/world", 1700848800)
+    };
+    store
+        .upsert_feed(
+            france24,
+            vec![
+                photo("https://www.france24.com/en/world", 1700848800),
+                relisted,
+            ],
+        )
+        .unwrap();
+    assert_eq!(store.counts().unwrap(), (2, 3));
+    assert_eq!(store.list().unwrap().len(), 3);
+    let listed = store.feed_photos(france24).unwrap();
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].image_url, "https://www.france24.com/en/world.jpg");
+    assert!(
+        store
+            .feed_photos("https://example.com/rss")
+            .unwrap()
+            .is_empty()
+    );
+
+    let by_feed: Vec<(String, usize)> = store
+        .photos_by_feed()
+        .unwrap()
+        .into_iter()
+        .map(|(url, photos)| (url, photos.len()))
+        .collect();
+    assert_eq!(
+        by_feed,
+        vec![(aeon.to_string(), 2), (france24.to_string(), 1)]
+    );
+
+    let found = store.get("https://aeon.co/essays/kept").unwrap().unwrap();
+    assert_eq!(found.first_seen, 1700841600);
+    assert_eq!(found.provenance, kept.provenance);
+    assert!(store.get("https://aeon.co/essays/gone").unwrap().is_none());
+
+    let seen: Vec<_> = store
+        .first_seen_between(1700841600, 1700852400)
+        .unwrap()
+        .into_iter()
+        .map(|p| p.story_url)
+        .collect();
+    assert_eq!(
+        seen,
+        vec![
+            "https://aeon.co/essays/kept",
+            "https://www.france24.com/en/world"
+        ]
+    );
+
+    assert_eq!(store.remove_feed(aeon).unwrap(), 2);
+    assert_eq!(store.remove_feed(aeon).unwrap(), 0);
+    store.upsert_feed(france24, Vec::new()).unwrap();
+    assert_eq!(store.counts().unwrap(), (0, 0));
+    assert!(store.feeds().unwrap().is_empty());
+}
+
+#[test]
+fn memory_stores_keep_photos_by_feed() {
+    check_store(&MemoryStore::default());
+}
+
+#[test]
+fn sqlite_stores_keep_photos_by_feed() {
+    check_store(&SqliteStore::in_memory().unwrap());
+}
+
+#[test]
+fn sqlite_stores_survive_a_restart() {
+    let path = std::env::temp_dir().join(format!(
+        "photojournalism-store-{}.sqlite",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_file(&path);
+    let feed = "https://aeon.co/feed.rss";
+    SqliteStore::open(&path)
+        .unwrap()
+        .upsert_feed(feed, vec![photo("https://aeon.co/essays/kept", 1700841600)])
+        .unwrap();
+
+    let store = SqliteStore::open(&path).unwrap();
+    assert_eq!(store.counts().unwrap(), (1, 1));
+    assert_eq!(store.feed_photos(feed).unwrap()[0].first_seen, 1700841600);
+    let _ = std::fs::remove_file(&path);
+}
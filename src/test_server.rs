@@ -0,0 +1,79 @@
+//! A bare HTTP/1.1 server on a local port, for the tests of whatever fetches over http:
+//! each test decides what to write back, down to hanging up or never answering.
+
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// The head of a request, as far as the tests care.
+pub struct Request {
+    pub path: String,
+    head: String,
+}
+
+impl Request {
+    /// The value of the header `name`, if it was sent.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.head.lines().find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            key.eq_ignore_ascii_case(name).then(|| value.trim())
+        })
+    }
+}
+
+/// Serves on a port of its own until the test ends, handing every request (each on a
+/// task of its own, so they are answered at once) to `respond` along with its connection.
+pub async fn serve<F, Fut>(respond: F) -> SocketAddr
+where
+    F: Fn(Request, TcpStream) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let respond = Arc::new(respond);
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            let respond = respond.clone();
+            tokio::spawn(async move {
+                let mut request = [0; 1024];
+                let read = socket.read(&mut request).await.unwrap_or_default();
+                let head = String::from_utf8_lossy(&request[..read]).to_string();
+                let path = head.split_whitespace().nth(1).unwrap_or_default();
+                let request = Request {
+                    path: path.to_string(),
+                    head,
+                };
+                respond(request, socket).await;
+            });
+        }
+    });
+    address
+}
+
+/// Serves on a port of its own, accepting connections but never answering them.
+pub async fn serve_nothing() -> SocketAddr {
+    serve(|_, socket| async move {
+        let _socket = socket;
+        std::future::pending::<()>().await;
+    })
+    .await
+}
+
+/// A whole response, kept out of any cache and closing the connection after it.
+pub fn response(status: &str, body: impl AsRef<[u8]>) -> Vec<u8> {
+    let body = body.as_ref();
+    let mut response = format!(
+        "HTTP/1.1 {status}\r\nCache-Control: no-store\r\nConnection: close\r\nContent-Length: {}\r\n\r\n",
+        body.len()
+    )
+    .into_bytes();
+    response.extend_from_slice(body);
+    response
+}
+
+/// Writes `response` down the connection, whether or not the client is still there.
+pub async fn reply(mut socket: TcpStream, response: impl AsRef<[u8]>) {
+    let _ = socket.write_all(response.as_ref()).await;
+}